@@ -1,12 +1,19 @@
 // /Users/snm/Equicom/workspace/NS/crates/moniof/src/config/global.rs
 
 use once_cell::sync::OnceCell;
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::observability::alert::AlertSink;
 
 // -------------------------------------------------------
 // Global Config Struct
 // -------------------------------------------------------
-#[derive(Clone, Default)]
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct MoniOFGlobalConfig {
     /// Log each DB command start/finish at DEBUG level
     pub log_each_db_event: bool,
@@ -17,56 +24,698 @@ pub struct MoniOFGlobalConfig {
     /// Suspiciously low DB command threshold (ms)
     pub low_db_threshold_ms: Option<u64>,
 
+    /// Adaptive slow detection for Mongo commands: slow = above the
+    /// (collection, op)'s own rolling p95 × this factor. Keys with too few
+    /// samples yet fall back to `slow_db_threshold_ms`. Must be > 1.0.
+    pub adaptive_slow_factor: Option<f64>,
+
+    /// Append the Mongo filter's sorted top-level field names to the key
+    /// (`users/find{_id}` vs `users/find{email}`), never the values. Off by
+    /// default: it multiplies key cardinality.
+    pub mongo_key_include_filter_fields: bool,
+
+    /// Report each (collection, op)'s first command in the process (which
+    /// pays connection/warmup cost) in `moniof_mongo_cold_command_seconds`
+    /// instead of the main command histogram. Keeps a process-wide map.
+    pub track_cold_commands: bool,
+
+    /// Quantiles (e.g. `[0.5, 0.9, 0.99]`) to export for Mongo command
+    /// latency as the `moniof_mongo_command_quantiles` summary, computed
+    /// over each (collection, op)'s latest 1,024 commands. Empty = off:
+    /// summaries cost more than the histogram. Each must be in (0, 1).
+    /// Read once, when the metrics registry is created (`init_prometheus`
+    /// or the first scrape): set it via `initiate` before then. Later
+    /// changes are logged and ignored.
+    pub mongo_command_quantiles: Vec<f64>,
+
+    /// `(pattern, label)` rules folding Mongo collection names into one
+    /// metric label, e.g. `("events_####_##", "events")` for monthly shards.
+    /// Patterns match the whole name; `*` = any run, `#` = one digit. First
+    /// match wins. Only the `collection` label changes, stats keys don't.
+    pub collection_label_rewrite: Vec<(String, String)>,
+
     /// Slack webhook URL for alerts (optional)
     pub slack_webhook: Option<String>,
+
+    /// Per-alert-kind Slack webhooks (`"mongo_failed"` -> #oncall, ...), see
+    /// `AlertMessage::kinds`. The first kind of an alert with a route wins;
+    /// unrouted alerts go to `slack_webhook`.
+    pub slack_routes: HashMap<String, String>,
+
+    /// Log alerts (target `moniof::dryrun`) instead of sending them, to
+    /// gauge alert volume before enabling Slack. Counted in
+    /// `moniof_alerts_sent_total{dry_run="true"}`.
+    pub alert_dry_run: bool,
+
+    /// Custom alert destination; takes precedence over `slack_webhook`.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub alert_sink: Option<Arc<dyn AlertSink>>,
+
+    /// Max length of a normalized SQL key (see `normalize_sql`)
+    pub sql_normalize_max_len: usize,
+
+    /// Key SQL queries by their statement template (bind placeholders and
+    /// literals folded to `?`) instead of `<table>/<op>`.
+    pub sql_group_by_template: bool,
+
+    /// Tracing target prefixes whose spans/events the SQL layer treats as
+    /// queries (statement in `db.statement`, `statement` or the message).
+    /// Add your own query code's targets, or narrow it to specific sqlx
+    /// targets. Needs the `sqlx` feature.
+    pub sql_targets: Vec<String>,
+
+    /// Also log the (redacted) command document with each DB event.
+    /// Only has an effect together with `log_each_db_event`.
+    pub log_db_command_body: bool,
+
+    /// Field names whose values are replaced with `"<redacted>"` before a
+    /// command document is logged (matched case-insensitively, at any depth).
+    pub redact_fields: Vec<String>,
+
+    /// Drop in-flight Mongo commands that never finished after this long (ms,
+    /// default 10 minutes). `None` disables the sweep.
+    ///
+    /// A swept command that does complete later is still counted, but as
+    /// `mongo/unknown/<op>` with ~0 ms latency, and its getMore cursor and
+    /// retried-write tracking are lost. Keep this well above your slowest
+    /// command (long aggregations, index builds).
+    pub inflight_max_age_ms: Option<u64>,
+
+    /// Let `initiate` install a global tracing subscriber (fmt + moniof layers).
+    /// Turn off when the app owns its subscriber and adds `moniof_layer()` itself.
+    pub install_subscriber: bool,
+
+    /// Kill switch: when `false`, middleware and DB hooks pass everything
+    /// through with no stats, metrics or alerts. Flip at runtime with
+    /// `update_global(|c| c.enabled = false)`.
+    pub enabled: bool,
+
+    /// Fail loudly instead of open: log instrumentation failures that are
+    /// normally silent (query outside any request scope, unwritable
+    /// header, metric never initialized, SQL layer not registered) at
+    /// `error` level. For development; off by default.
+    pub strict: bool,
+
+    /// Datadog agent (`host:port`, usually `127.0.0.1:8125`) to mirror metrics
+    /// to over DogStatsD. Only used with the `datadog` feature; `None` = off.
+    pub dogstatsd_addr: Option<String>,
+
+    /// Request dimension (set by handlers with `set_dimension`, e.g.
+    /// `"tenant"`) to label `moniof_request_queries` with. `None` = every
+    /// request is labeled `"unset"`.
+    pub query_count_dimension: Option<String>,
+    /// Distinct dimension values emitted as labels; later values are
+    /// reported as `"other"` to bound series cardinality.
+    pub max_dimension_values: usize,
+
+    /// Deployment environment (`"staging"`, `"prod"`, ...) added as an
+    /// `environment` label to every Prometheus series. Read once, when the
+    /// registry is created, so set it via `initiate` before the middleware
+    /// is built. Later changes are logged and ignored.
+    pub environment: Option<String>,
+}
+
+impl Default for MoniOFGlobalConfig {
+    fn default() -> Self {
+        Self {
+            log_each_db_event: false,
+            slow_db_threshold_ms: None,
+            low_db_threshold_ms: None,
+            adaptive_slow_factor: None,
+            mongo_key_include_filter_fields: false,
+            track_cold_commands: false,
+            collection_label_rewrite: Vec::new(),
+            mongo_command_quantiles: Vec::new(),
+            slack_webhook: None,
+            slack_routes: HashMap::new(),
+            alert_dry_run: false,
+            alert_sink: None,
+            sql_normalize_max_len: 200,
+            sql_group_by_template: false,
+            sql_targets: vec!["sqlx::query".to_string()],
+            log_db_command_body: false,
+            redact_fields: vec!["password".to_string(), "token".to_string(), "ssn".to_string()],
+            inflight_max_age_ms: Some(600_000),
+            install_subscriber: true,
+            enabled: true,
+            strict: false,
+            dogstatsd_addr: None,
+            query_count_dimension: None,
+            max_dimension_values: 100,
+            environment: None,
+        }
+    }
+}
+
+// -------------------------------------------------------
+// Validation
+// -------------------------------------------------------
+/// A `MoniOFGlobalConfig` (or `MoniOFConfig`) that would misbehave if applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    /// `low_db_threshold_ms >= slow_db_threshold_ms`: every query would be both.
+    LowNotBelowSlow { low_ms: u64, slow_ms: u64 },
+    /// `slack_webhook` is set but blank.
+    EmptySlackWebhook,
+    /// A `slack_routes` entry (for the given alert kind) has a blank URL.
+    EmptySlackRoute(String),
+    /// `sql_normalize_max_len` is 0, so every SQL query maps to one key.
+    ZeroSqlNormalizeMaxLen,
+    /// `adaptive_slow_factor` is not a finite number above 1.0, so typical
+    /// commands would be flagged as slow.
+    AdaptiveSlowFactorNotAboveOne,
+    /// A `mongo_command_quantiles` entry is not strictly between 0 and 1.
+    QuantileOutOfRange,
+    /// `MoniOFConfig::sample_rate` is NaN or outside [0, 1].
+    SampleRateOutOfRange,
+    /// `MoniOFConfig::warn_dominant_key_fraction` is NaN or outside (0, 1]:
+    /// every request would warn, or none could.
+    DominantKeyFractionOutOfRange,
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::LowNotBelowSlow { low_ms, slow_ms } => write!(
+                f,
+                "low_db_threshold_ms ({low_ms}) must be below slow_db_threshold_ms ({slow_ms})"
+            ),
+            ConfigError::EmptySlackWebhook => write!(f, "slack_webhook is set but empty"),
+            ConfigError::EmptySlackRoute(kind) => write!(f, "slack_routes[{kind:?}] is empty"),
+            ConfigError::ZeroSqlNormalizeMaxLen => write!(f, "sql_normalize_max_len must be > 0"),
+            ConfigError::AdaptiveSlowFactorNotAboveOne => {
+                write!(f, "adaptive_slow_factor must be a finite number > 1.0")
+            }
+            ConfigError::QuantileOutOfRange => {
+                write!(f, "mongo_command_quantiles entries must be in (0, 1)")
+            }
+            ConfigError::SampleRateOutOfRange => write!(f, "sample_rate must be in [0, 1]"),
+            ConfigError::DominantKeyFractionOutOfRange => {
+                write!(f, "warn_dominant_key_fraction must be in (0, 1]")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl MoniOFGlobalConfig {
+    pub fn builder() -> MoniOFGlobalConfigBuilder {
+        MoniOFGlobalConfigBuilder::default()
+    }
+
+    /// Check for combinations that make alerts meaningless.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if let (Some(low_ms), Some(slow_ms)) = (self.low_db_threshold_ms, self.slow_db_threshold_ms) {
+            if low_ms >= slow_ms {
+                return Err(ConfigError::LowNotBelowSlow { low_ms, slow_ms });
+            }
+        }
+        if let Some(ref hook) = self.slack_webhook {
+            if hook.trim().is_empty() {
+                return Err(ConfigError::EmptySlackWebhook);
+            }
+        }
+        if let Some((kind, _)) = self.slack_routes.iter().find(|(_, url)| url.trim().is_empty()) {
+            return Err(ConfigError::EmptySlackRoute(kind.clone()));
+        }
+        if self.sql_normalize_max_len == 0 {
+            return Err(ConfigError::ZeroSqlNormalizeMaxLen);
+        }
+        if let Some(factor) = self.adaptive_slow_factor {
+            if !(factor.is_finite() && factor > 1.0) {
+                return Err(ConfigError::AdaptiveSlowFactorNotAboveOne);
+            }
+        }
+        if self.mongo_command_quantiles.iter().any(|q| !(*q > 0.0 && *q < 1.0)) {
+            return Err(ConfigError::QuantileOutOfRange);
+        }
+        Ok(())
+    }
+}
+
+// -------------------------------------------------------
+// Environment
+// -------------------------------------------------------
+impl MoniOFGlobalConfig {
+    /// Build a config from environment variables; unset vars keep `Default`.
+    ///
+    /// | var                            | type                          |
+    /// |--------------------------------|-------------------------------|
+    /// | `MONIOF_LOG_EACH_DB_EVENT`     | bool (`1/true/yes/on`)        |
+    /// | `MONIOF_SLOW_DB_MS`            | u64                           |
+    /// | `MONIOF_LOW_DB_MS`             | u64                           |
+    /// | `MONIOF_ADAPTIVE_SLOW_FACTOR`  | f64 (> 1.0)                   |
+    /// | `MONIOF_MONGO_FILTER_KEYS`     | bool                          |
+    /// | `MONIOF_TRACK_COLD_COMMANDS`   | bool                          |
+    /// | `MONIOF_MONGO_QUANTILES`       | comma-separated f64s          |
+    /// | `MONIOF_SLACK_WEBHOOK`         | string                        |
+    /// | `MONIOF_SLACK_ROUTES`          | `kind=url,kind=url`           |
+    /// | `MONIOF_ALERT_DRY_RUN`         | bool                          |
+    /// | `MONIOF_SQL_NORMALIZE_MAX_LEN` | usize                         |
+    /// | `MONIOF_SQL_GROUP_BY_TEMPLATE` | bool                          |
+    /// | `MONIOF_SQL_TARGETS`           | comma-separated list          |
+    /// | `MONIOF_LOG_DB_COMMAND_BODY`   | bool                          |
+    /// | `MONIOF_REDACT_FIELDS`         | comma-separated list          |
+    /// | `MONIOF_INFLIGHT_MAX_AGE_MS`   | u64, or `off` to disable      |
+    /// | `MONIOF_INSTALL_SUBSCRIBER`    | bool                          |
+    /// | `MONIOF_ENABLED`               | bool                          |
+    /// | `MONIOF_STRICT`                | bool                          |
+    /// | `MONIOF_DOGSTATSD_ADDR`        | `host:port`                   |
+    /// | `MONIOF_QUERY_COUNT_DIMENSION` | string (e.g. `tenant`)        |
+    /// | `MONIOF_ENVIRONMENT`           | string (e.g. `prod`)          |
+    ///
+    /// Values that fail to parse are logged and ignored.
+    pub fn from_env() -> Self {
+        let mut cfg = Self::default();
+
+        if let Some(v) = env_parse("MONIOF_LOG_EACH_DB_EVENT", parse_bool) {
+            cfg.log_each_db_event = v;
+        }
+        if let Some(v) = env_parse("MONIOF_SLOW_DB_MS", |s| s.parse().ok()) {
+            cfg.slow_db_threshold_ms = Some(v);
+        }
+        if let Some(v) = env_parse("MONIOF_LOW_DB_MS", |s| s.parse().ok()) {
+            cfg.low_db_threshold_ms = Some(v);
+        }
+        if let Some(v) = env_parse("MONIOF_ADAPTIVE_SLOW_FACTOR", |s| s.parse().ok()) {
+            cfg.adaptive_slow_factor = Some(v);
+        }
+        if let Some(v) = env_parse("MONIOF_MONGO_FILTER_KEYS", parse_bool) {
+            cfg.mongo_key_include_filter_fields = v;
+        }
+        if let Some(v) = env_parse("MONIOF_TRACK_COLD_COMMANDS", parse_bool) {
+            cfg.track_cold_commands = v;
+        }
+        if let Some(v) = env_parse("MONIOF_MONGO_QUANTILES", |s| {
+            s.split(',')
+                .map(str::trim)
+                .filter(|q| !q.is_empty())
+                .map(|q| q.parse().ok())
+                .collect::<Option<Vec<f64>>>()
+        }) {
+            cfg.mongo_command_quantiles = v;
+        }
+        if let Some(v) = env_parse("MONIOF_SLACK_WEBHOOK", |s| Some(s.to_string())) {
+            cfg.slack_webhook = Some(v);
+        }
+        if let Some(v) = env_parse("MONIOF_SLACK_ROUTES", |s| {
+            s.split(',')
+                .filter(|r| !r.trim().is_empty())
+                .map(|r| {
+                    let (kind, url) = r.split_once('=')?;
+                    Some((kind.trim().to_string(), url.trim().to_string()))
+                })
+                .collect::<Option<HashMap<_, _>>>()
+        }) {
+            cfg.slack_routes = v;
+        }
+        if let Some(v) = env_parse("MONIOF_ALERT_DRY_RUN", parse_bool) {
+            cfg.alert_dry_run = v;
+        }
+        if let Some(v) = env_parse("MONIOF_SQL_NORMALIZE_MAX_LEN", |s| s.parse().ok()) {
+            cfg.sql_normalize_max_len = v;
+        }
+        if let Some(v) = env_parse("MONIOF_SQL_GROUP_BY_TEMPLATE", parse_bool) {
+            cfg.sql_group_by_template = v;
+        }
+        if let Some(v) = env_parse("MONIOF_SQL_TARGETS", |s| {
+            Some(s.split(',').map(str::trim).filter(|t| !t.is_empty()).map(String::from).collect())
+        }) {
+            cfg.sql_targets = v;
+        }
+        if let Some(v) = env_parse("MONIOF_LOG_DB_COMMAND_BODY", parse_bool) {
+            cfg.log_db_command_body = v;
+        }
+        if let Some(v) = env_parse("MONIOF_REDACT_FIELDS", |s| {
+            Some(s.split(',').map(str::trim).filter(|f| !f.is_empty()).map(String::from).collect())
+        }) {
+            cfg.redact_fields = v;
+        }
+        if let Some(v) = env_parse("MONIOF_INFLIGHT_MAX_AGE_MS", |s| match s {
+            "off" | "none" => Some(None),
+            _ => s.parse().ok().map(Some),
+        }) {
+            cfg.inflight_max_age_ms = v;
+        }
+        if let Some(v) = env_parse("MONIOF_INSTALL_SUBSCRIBER", parse_bool) {
+            cfg.install_subscriber = v;
+        }
+        if let Some(v) = env_parse("MONIOF_ENABLED", parse_bool) {
+            cfg.enabled = v;
+        }
+        if let Some(v) = env_parse("MONIOF_STRICT", parse_bool) {
+            cfg.strict = v;
+        }
+        if let Some(v) = env_parse("MONIOF_DOGSTATSD_ADDR", |s| Some(s.to_string())) {
+            cfg.dogstatsd_addr = Some(v);
+        }
+        if let Some(v) = env_parse("MONIOF_QUERY_COUNT_DIMENSION", |s| Some(s.to_string())) {
+            cfg.query_count_dimension = Some(v);
+        }
+        if let Some(v) = env_parse("MONIOF_ENVIRONMENT", |s| Some(s.to_string())) {
+            cfg.environment = Some(v);
+        }
+
+        cfg
+    }
+}
+
+fn env_parse<T>(name: &str, parse: impl Fn(&str) -> Option<T>) -> Option<T> {
+    let raw = std::env::var(name).ok()?;
+    let raw = raw.trim();
+    let parsed = parse(raw);
+    if parsed.is_none() {
+        tracing::warn!(target = "moniof", var = name, value = raw, "ignoring unparseable env var");
+    }
+    parsed
+}
+
+fn parse_bool(s: &str) -> Option<bool> {
+    match s.to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+/// Builder for [`MoniOFGlobalConfig`]; `build()` validates.
+#[derive(Clone, Default)]
+pub struct MoniOFGlobalConfigBuilder {
+    cfg: MoniOFGlobalConfig,
+}
+
+impl MoniOFGlobalConfigBuilder {
+    pub fn log_each_db_event(mut self, on: bool) -> Self {
+        self.cfg.log_each_db_event = on;
+        self
+    }
+
+    pub fn slow_db_threshold_ms(mut self, ms: u64) -> Self {
+        self.cfg.slow_db_threshold_ms = Some(ms);
+        self
+    }
+
+    pub fn low_db_threshold_ms(mut self, ms: u64) -> Self {
+        self.cfg.low_db_threshold_ms = Some(ms);
+        self
+    }
+
+    pub fn adaptive_slow_factor(mut self, factor: f64) -> Self {
+        self.cfg.adaptive_slow_factor = Some(factor);
+        self
+    }
+
+    pub fn mongo_key_include_filter_fields(mut self, on: bool) -> Self {
+        self.cfg.mongo_key_include_filter_fields = on;
+        self
+    }
+
+    /// Send alerts of `kind` (e.g. `"sql_failed"`) to `url` instead of
+    /// `slack_webhook`.
+    pub fn slack_route(mut self, kind: impl Into<String>, url: impl Into<String>) -> Self {
+        self.cfg.slack_routes.insert(kind.into(), url.into());
+        self
+    }
+
+    pub fn track_cold_commands(mut self, on: bool) -> Self {
+        self.cfg.track_cold_commands = on;
+        self
+    }
+
+    pub fn mongo_command_quantiles(mut self, quantiles: impl IntoIterator<Item = f64>) -> Self {
+        self.cfg.mongo_command_quantiles = quantiles.into_iter().collect();
+        self
+    }
+
+    /// Add a `collection_label_rewrite` rule (`pattern` -> `label`).
+    pub fn collection_label_rewrite(
+        mut self,
+        pattern: impl Into<String>,
+        label: impl Into<String>,
+    ) -> Self {
+        self.cfg.collection_label_rewrite.push((pattern.into(), label.into()));
+        self
+    }
+
+    pub fn slack_webhook(mut self, url: impl Into<String>) -> Self {
+        self.cfg.slack_webhook = Some(url.into());
+        self
+    }
+
+    pub fn alert_dry_run(mut self, on: bool) -> Self {
+        self.cfg.alert_dry_run = on;
+        self
+    }
+
+    pub fn alert_sink(mut self, sink: Arc<dyn AlertSink>) -> Self {
+        self.cfg.alert_sink = Some(sink);
+        self
+    }
+
+    pub fn sql_normalize_max_len(mut self, len: usize) -> Self {
+        self.cfg.sql_normalize_max_len = len;
+        self
+    }
+
+    pub fn sql_group_by_template(mut self, on: bool) -> Self {
+        self.cfg.sql_group_by_template = on;
+        self
+    }
+
+    pub fn sql_targets<I, S>(mut self, targets: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.cfg.sql_targets = targets.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn log_db_command_body(mut self, on: bool) -> Self {
+        self.cfg.log_db_command_body = on;
+        self
+    }
+
+    pub fn redact_fields<I, S>(mut self, fields: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.cfg.redact_fields = fields.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// `None` disables the in-flight sweep.
+    pub fn inflight_max_age_ms(mut self, ms: Option<u64>) -> Self {
+        self.cfg.inflight_max_age_ms = ms;
+        self
+    }
+
+    pub fn install_subscriber(mut self, on: bool) -> Self {
+        self.cfg.install_subscriber = on;
+        self
+    }
+
+    pub fn enabled(mut self, on: bool) -> Self {
+        self.cfg.enabled = on;
+        self
+    }
+
+    pub fn strict(mut self, on: bool) -> Self {
+        self.cfg.strict = on;
+        self
+    }
+
+    pub fn dogstatsd_addr(mut self, addr: impl Into<String>) -> Self {
+        self.cfg.dogstatsd_addr = Some(addr.into());
+        self
+    }
+
+    pub fn query_count_dimension(mut self, name: impl Into<String>) -> Self {
+        self.cfg.query_count_dimension = Some(name.into());
+        self
+    }
+
+    pub fn max_dimension_values(mut self, n: usize) -> Self {
+        self.cfg.max_dimension_values = n;
+        self
+    }
+
+    pub fn environment(mut self, env: impl Into<String>) -> Self {
+        self.cfg.environment = Some(env.into());
+        self
+    }
+
+    pub fn build(self) -> Result<MoniOFGlobalConfig, ConfigError> {
+        self.cfg.validate()?;
+        Ok(self.cfg)
+    }
 }
 
 static GLOBAL: OnceCell<RwLock<MoniOFGlobalConfig>> = OnceCell::new();
 
+/// Mirror of `enabled`, read on every request / DB event without cloning
+/// the config.
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Whether instrumentation is on (the `enabled` kill switch).
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Mirror of `strict`.
+static STRICT: AtomicBool = AtomicBool::new(false);
+
+/// Whether silent instrumentation failures are logged as errors (`strict`).
+pub fn is_strict() -> bool {
+    STRICT.load(Ordering::Relaxed)
+}
 
 // -------------------------------------------------------
 // INITIATE (GLOBAL INIT + TRACING SETUP)
 // -------------------------------------------------------
+/// Apply the global config and, when `install_subscriber` is set (the
+/// default), install a global tracing subscriber with moniof's layers.
+///
+/// SQL instrumentation only works if `MOFSqlEvents` is part of the *active*
+/// subscriber. If your app installs its own subscriber, set
+/// `install_subscriber: false` and add `moniof::moniof_layer()` to it.
 pub fn initiate(cfg: MoniOFGlobalConfig) {
-    use tracing_subscriber::{fmt, EnvFilter, prelude::*};
+    let filter = cfg.install_subscriber.then(default_filter);
+    initiate_inner(cfg, filter);
+}
 
+/// Like `initiate`, but installs the subscriber with the caller's `filter`
+/// instead of moniof's default directives (`moniof=debug`, `sqlx=info`, ...),
+/// so moniof's own log lines can be turned down without losing the SQL layer.
+///
+/// ```ignore
+/// let filter = EnvFilter::from_default_env().add_directive("moniof=warn".parse()?);
+/// moniof::initiate_with_filter(cfg, filter);
+/// ```
+///
+/// The subscriber is installed regardless of `install_subscriber`.
+pub fn initiate_with_filter(cfg: MoniOFGlobalConfig, filter: tracing_subscriber::EnvFilter) {
+    initiate_inner(cfg, Some(filter));
+}
+
+fn initiate_inner(cfg: MoniOFGlobalConfig, filter: Option<tracing_subscriber::EnvFilter>) {
+    if let Some(filter) = filter {
+        install_subscriber(filter);
+    }
+
+    match cfg.validate() {
+        Ok(()) => {
+            let _writer = WRITER.lock();
+            apply(cfg);
+        }
+        Err(e) => tracing::error!(
+            target = "moniof",
+            error = %e,
+            "invalid moniof global config rejected; keeping the previous one"
+        ),
+    }
+
+    #[cfg(feature = "mongodb")]
+    crate::instrumentation::mongo_events::start_inflight_sweeper();
+
+    tracing::info!(target = "moniof", "moniof global initiated");
+}
+
+/// RUST_LOG plus moniof's fallback directives.
+fn default_filter() -> tracing_subscriber::EnvFilter {
     // Build RUST_LOG + moniof fallback filter
-    let base = EnvFilter::from_default_env();
+    let base = tracing_subscriber::EnvFilter::from_default_env();
 
-    let filter = base
+    base
         .add_directive("moniof=debug".parse().unwrap_or_else(|_| "debug".parse().unwrap()))
         .add_directive("moniof::mongo=debug".parse().unwrap_or_else(|_| "debug".parse().unwrap()))
         .add_directive("moniof::sql=debug".parse().unwrap_or_else(|_| "debug".parse().unwrap()))
         .add_directive("moniof::of=debug".parse().unwrap_or_else(|_| "debug".parse().unwrap()))
-        .add_directive("sqlx=info".parse().unwrap_or_else(|_| "info".parse().unwrap())); // SQLx internal logs
+        .add_directive("sqlx=info".parse().unwrap_or_else(|_| "info".parse().unwrap())) // SQLx internal logs
+}
+
+fn install_subscriber(filter: tracing_subscriber::EnvFilter) {
+    use tracing_subscriber::{fmt, prelude::*};
 
     let fmt_layer = fmt::layer().with_target(true);
 
     #[cfg(feature = "sqlx")]
-    {
-        use crate::instrumentation::sql_events::MOFSqlEvents;
+    let installed = tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt_layer)
+        .with(crate::instrumentation::sql_events::moniof_layer()) // ADD SQL INSTRUMENTATION HERE
+        .try_init();
 
-        let subscriber = tracing_subscriber::registry()
-            .with(filter)
-            .with(fmt_layer)
-            .with(MOFSqlEvents::new()); // ADD SQL INSTRUMENTATION HERE
+    #[cfg(not(feature = "sqlx"))]
+    let installed = tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt_layer)
+        .try_init();
 
-        let _ = subscriber.try_init();
+    if installed.is_err() {
+        tracing::warn!(
+            target = "moniof",
+            "a global tracing subscriber is already set; moniof did not install its own. \
+             Add moniof::moniof_layer() to your subscriber for SQL instrumentation"
+        );
     }
+}
 
-    #[cfg(not(feature = "sqlx"))]
-    {
-        let subscriber = tracing_subscriber::registry()
-            .with(filter)
-            .with(fmt_layer);
+// -------------------------------------------------------
+// LIVE RECONFIGURATION
+// -------------------------------------------------------
+/// Replace the global config at runtime (no tracing setup).
+/// Event handlers read `global()` per event, so the change applies immediately.
+pub fn set_global(cfg: MoniOFGlobalConfig) -> Result<(), ConfigError> {
+    cfg.validate()?;
+    let _writer = WRITER.lock();
+    apply(cfg);
+    Ok(())
+}
 
-        let _ = subscriber.try_init();
-    }
+/// Mutate the global config in place, e.g. to raise `slow_db_threshold_ms`
+/// during a maintenance window. The result is validated first; on error the
+/// current config is left untouched.
+///
+/// `f` runs on a copy, outside the config lock, so it may call `global()`;
+/// it must not call `set_global` / `update_global` itself.
+pub fn update_global(f: impl FnOnce(&mut MoniOFGlobalConfig)) -> Result<(), ConfigError> {
+    let _writer = WRITER.lock();
+    let mut next = global();
+    f(&mut next);
+    next.validate()?;
+    apply(next);
+    Ok(())
+}
+
+/// Serializes writers so `update_global`'s read-modify-write can't lose a
+/// concurrent update; readers only ever take `GLOBAL`'s read lock.
+static WRITER: Mutex<()> = Mutex::new(());
 
+/// Swap `cfg` in under a short write lock, then push its side effects
+/// (which may block, e.g. resolving the DogStatsD host) with no lock held.
+/// Callers hold `WRITER`.
+fn apply(cfg: MoniOFGlobalConfig) {
     let cell = GLOBAL.get_or_init(|| RwLock::new(MoniOFGlobalConfig::default()));
+    let applied = cfg.clone();
     *cell.write() = cfg;
+    on_applied(&applied);
+}
+
+/// Push settings that live outside `GLOBAL` (kill switch, backend clients)
+/// into effect.
+fn on_applied(cfg: &MoniOFGlobalConfig) {
+    ENABLED.store(cfg.enabled, Ordering::Relaxed);
+    STRICT.store(cfg.strict, Ordering::Relaxed);
+    crate::observability::prom::warn_if_registry_settings_changed(cfg);
 
-    tracing::info!(target = "moniof", "moniof global initiated (SQL logging enabled)");
+    #[cfg(feature = "datadog")]
+    crate::observability::dogstatsd::configure(cfg.dogstatsd_addr.as_deref());
+
+    #[cfg(feature = "sqlx")]
+    crate::instrumentation::sql_events::configure_targets(&cfg.sql_targets);
 }
 
 // -------------------------------------------------------
@@ -78,3 +727,63 @@ pub fn global() -> MoniOFGlobalConfig {
         .map(|g| g.read().clone())
         .unwrap_or_default()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sets env vars for the guard's lifetime and restores them on drop.
+    /// `from_env` is the only reader of `MONIOF_*` in the unit tests.
+    struct EnvGuard(Vec<(&'static str, Option<String>)>);
+
+    impl EnvGuard {
+        fn set(vars: &[(&'static str, &str)]) -> Self {
+            let saved = vars.iter().map(|(k, _)| (*k, std::env::var(k).ok())).collect();
+            for (k, v) in vars {
+                std::env::set_var(k, v);
+            }
+            Self(saved)
+        }
+    }
+
+    impl Drop for EnvGuard {
+        fn drop(&mut self) {
+            for (k, v) in &self.0 {
+                match v {
+                    Some(v) => std::env::set_var(k, v),
+                    None => std::env::remove_var(k),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn from_env_reads_set_vars_and_ignores_unparseable_ones() {
+        let _env = EnvGuard::set(&[
+            ("MONIOF_SLOW_DB_MS", "250"),
+            ("MONIOF_LOW_DB_MS", "not-a-number"),
+            ("MONIOF_STRICT", "yes"),
+            ("MONIOF_MONGO_QUANTILES", "0.5, 0.99"),
+            ("MONIOF_SLACK_ROUTES", "sql_failed=https://hooks.example/sql"),
+            ("MONIOF_INFLIGHT_MAX_AGE_MS", "off"),
+        ]);
+        let cfg = MoniOFGlobalConfig::from_env();
+        let default = MoniOFGlobalConfig::default();
+
+        assert_eq!(cfg.slow_db_threshold_ms, Some(250));
+        assert_eq!(cfg.low_db_threshold_ms, default.low_db_threshold_ms);
+        assert!(cfg.strict);
+        assert_eq!(cfg.mongo_command_quantiles, vec![0.5, 0.99]);
+        assert_eq!(
+            cfg.slack_routes.get("sql_failed").map(String::as_str),
+            Some("https://hooks.example/sql")
+        );
+        assert_eq!(cfg.inflight_max_age_ms, None);
+    }
+
+    #[test]
+    fn validate_rejects_an_empty_slack_route() {
+        let cfg = MoniOFGlobalConfig::builder().slack_route("sql_failed", "  ").build();
+        assert_eq!(cfg.err(), Some(ConfigError::EmptySlackRoute("sql_failed".to_string())));
+    }
+}
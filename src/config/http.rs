@@ -1,20 +1,231 @@
+use crate::config::{ConfigError, MoniOFGlobalConfig};
+use crate::services::lifecycle::RequestSummary;
+
+use std::fmt;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+/// Callback run with each request's [`RequestSummary`]; see
+/// [`MoniOFConfig::on_request_complete`].
+#[derive(Clone)]
+pub struct RequestHook(Arc<dyn Fn(&RequestSummary) + Send + Sync>);
+
+impl RequestHook {
+    pub fn new(f: impl Fn(&RequestSummary) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(f))
+    }
+
+    pub fn call(&self, summary: &RequestSummary) {
+        (self.0)(summary)
+    }
+}
+
+impl fmt::Debug for RequestHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("RequestHook(..)")
+    }
+}
+
+/// Route label for requests without a matched route pattern (dynamic
+/// routing, 404s); see [`MoniOFConfig::path_normalizer`].
+#[derive(Clone)]
+pub struct PathNormalizer(Arc<dyn Fn(&str) -> String + Send + Sync>);
+
+impl PathNormalizer {
+    pub fn new(f: impl Fn(&str) -> String + Send + Sync + 'static) -> Self {
+        Self(Arc::new(f))
+    }
+
+    /// The built-in rules, see [`normalize_path`].
+    pub fn ids() -> Self {
+        Self::new(normalize_path)
+    }
+
+    pub fn normalize(&self, path: &str) -> String {
+        (self.0)(path)
+    }
+}
+
+impl fmt::Debug for PathNormalizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PathNormalizer(..)")
+    }
+}
+
+/// Collapse id-like path segments to `:id`: integers, UUIDs and hex strings
+/// of 16+ characters (Mongo ObjectIds, hashes).
+/// `/users/123/orders/5f0c...e1` -> `/users/:id/orders/:id`.
+pub fn normalize_path(path: &str) -> String {
+    path.split('/')
+        .map(|seg| if is_id_segment(seg) { ":id" } else { seg })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn is_id_segment(seg: &str) -> bool {
+    let is_hex = |s: &str| s.bytes().all(|b| b.is_ascii_hexdigit());
+    if seg.is_empty() {
+        return false;
+    }
+    if seg.bytes().all(|b| b.is_ascii_digit()) {
+        return true;
+    }
+    let uuid_groups: Vec<&str> = seg.split('-').collect();
+    if uuid_groups.iter().map(|g| g.len()).eq([8, 4, 4, 4, 12]) {
+        return uuid_groups.iter().all(|g| is_hex(g));
+    }
+    seg.len() >= 16 && is_hex(seg)
+}
+
+/// How N+1 suspects are gated and ranked; see
+/// [`MoniOFConfig::n_plus_one_rank_by`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum NPlusOneRank {
+    /// At least `n_plus_one_min_count` repeats (and `n_plus_one_min_total_ms`,
+    /// if set); most repeated first.
+    #[default]
+    Count,
+    /// At least `n_plus_one_min_total_ms` spent on a key queried more than
+    /// once; most expensive first. The count gate doesn't apply, so set the
+    /// latency gate to what an expensive loop means for you: with
+    /// `n_plus_one_min_total_ms: None`, every key queried twice or more is
+    /// a suspect.
+    TotalLatency,
+    /// Both gates, as with `Count`, ranked by `count × total_latency_ms`.
+    Score,
+}
+
+/// Millisecond thresholds are `u128` (like `Duration::as_millis`), which
+/// TOML and other formats can't represent; read them as `u64`.
+#[cfg(feature = "serde")]
+fn opt_u128<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Option<u128>, D::Error> {
+    use serde::Deserialize;
+    Ok(Option::<u64>::deserialize(d)?.map(u128::from))
+}
+
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct MoniOFConfig {
     pub max_total: usize,
     pub max_same_key: usize,
     pub add_response_headers: bool,
     pub log_warnings: bool,
-    /// Warn when *cumulative* DB latency exceeds this (ms)
+    /// Warn when *cumulative* DB latency exceeds this (ms).
+    /// Takes precedence; when unset, see `expected_queries_per_request`.
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "opt_u128"))]
     pub warn_total_db_latency_ms: Option<u128>,
     /// Alert when *cumulative* DB latency is unusually low (ms) but queries > 0
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "opt_u128"))]
     pub warn_low_total_db_latency_ms: Option<u128>,
+    /// Warn when the slowest single query in a request takes this long (ms).
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "opt_u128"))]
+    pub warn_slowest_query_ms: Option<u128>,
+    /// Warn when the slowest key's max latency is at least this fraction of
+    /// the request's wall time (e.g. `0.8`): one query is the biggest win.
+    /// Must be in (0, 1].
+    pub warn_dominant_key_fraction: Option<f64>,
+    /// The dominant-key warning also needs that query to take at least this
+    /// long (ms) and `min_total_for_alert` queries, so a 2 ms request with
+    /// one 1.9 ms query doesn't warn. `None` = no floor.
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "opt_u128"))]
+    pub warn_dominant_key_min_ms: Option<u128>,
+    /// How many of the slowest keys (by max latency) to report. Above 1,
+    /// they're also sent as `x-moniof-slow-<n>-key` / `-latency-ms` headers
+    /// (at most 10) and all listed in Slack alerts.
+    pub slowest_key_count: usize,
+    /// Top collections/tables (by query count) listed in the
+    /// `x-moniof-by-collection` header, e.g. `users=40,orders=3`.
+    /// 0 = no header (the default; it can get long).
+    pub collection_counts_header: usize,
+    /// Requests faster than this (ms) only observe the request counter and
+    /// histograms; N+1 scanning, slowest-key scans, headers, warnings and
+    /// `on_request_complete` are skipped. `None` = analyze every request.
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "opt_u128"))]
+    pub analyze_only_above_ms: Option<u128>,
+    /// Requests with fewer queries skip the cumulative-latency, same-key and
+    /// N+1 warnings (a blip on a 2-query endpoint isn't worth a page). The
+    /// low-latency and slowest-query warnings are not gated. 0 = no gate.
+    pub min_total_for_alert: usize,
+
+    /// Page Slack only after the same route trips the same alert kind this
+    /// many times within `escalate_window_ms`; every occurrence is still
+    /// logged. 1 = page every time.
+    pub escalate_after: usize,
+    /// Escalation window (ms), starting at the first occurrence.
+    pub escalate_window_ms: u64,
 
     /// OF-style N+1 detection
     pub of_mode: bool,
     /// Minimum times a key must repeat in a request to be considered N+1.
     pub n_plus_one_min_count: usize,
     /// Optional minimum total latency for that key to be considered N+1.
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "opt_u128"))]
     pub n_plus_one_min_total_ms: Option<u128>,
+    /// Gate and order N+1 suspects by count (default), total latency or
+    /// both: six 0.2 ms lookups are noise, six 80 ms ones aren't.
+    pub n_plus_one_rank_by: NPlusOneRank,
+
+    /// Keep an ordered log of queries per request (key + offset ms),
+    /// so reports can show consecutive runs of the same key.
+    pub record_query_order: bool,
+    /// Max events kept in the ordered log; extra events are dropped.
+    pub query_order_max_events: usize,
+
+    /// Longest `x-moniof-*` header value (bytes); longer values (e.g. long
+    /// SQL keys) are truncated with `...`. 0 = no limit.
+    pub max_header_value_len: usize,
+
+    /// Cap on distinct query keys per request (guards memory against
+    /// un-normalized keys); past it, new keys fold into `<kind>/<overflow>`
+    /// and a warning is logged. 0 = unbounded.
+    pub max_distinct_keys: usize,
+
+    /// When `warn_total_db_latency_ms` is unset, derive it from the global
+    /// per-command `slow_db_threshold_ms × expected_queries_per_request`.
+    pub expected_queries_per_request: Option<usize>,
+
+    /// Route label (alerts, escalation, summaries, leaderboard) for
+    /// requests without a matched route pattern, from the raw path.
+    /// `PathNormalizer::ids()` collapses numeric/UUID segments; `None`
+    /// (default) labels them `unmatched`.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub path_normalizer: Option<PathNormalizer>,
+
+    /// Paths passed straight through without stats, metrics or alerts
+    /// (e.g. `/metrics`, `/healthz`). Exact match, or prefix match when the
+    /// entry ends with `*` (`/internal/*`).
+    pub skip_paths: Vec<String>,
+    /// HTTP methods passed straight through the same way (e.g. `OPTIONS`
+    /// preflights, `HEAD`). Matched case-insensitively.
+    pub skip_methods: Vec<String>,
+
+    /// Fraction of requests (0.0–1.0) that get full per-query stats, N+1
+    /// analysis and alerts. The rest only feed the HTTP counter/histogram.
+    /// Sampling trades completeness for throughput on high-QPS services.
+    pub sample_rate: f64,
+
+    /// Emit one structured `info` event (target `moniof::summary`) per
+    /// request with its totals, whether or not an alert fired.
+    pub emit_request_summary: bool,
+
+    /// Wrap each request in a `moniof.request` tracing span (method, route,
+    /// request ID; status, total, db_total_ms recorded at the end) so DB
+    /// events and warnings group under it. Off by default.
+    pub request_span: bool,
+
+    /// Called at the end of every tracked request with a read-only summary,
+    /// to forward stats to a custom sink. Panics are caught and logged.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub on_request_complete: Option<RequestHook>,
+
+    /// Channel that receives every tracked request's summary, for pipelines
+    /// that don't go through `tracing`. Sent with `try_send`: when the
+    /// channel is full the summary is dropped, never the response delayed.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub summary_sink: Option<mpsc::Sender<RequestSummary>>,
 }
 
 impl Default for MoniOFConfig {
@@ -26,10 +237,347 @@ impl Default for MoniOFConfig {
             log_warnings: true,
             warn_total_db_latency_ms: None,
             warn_low_total_db_latency_ms: None,
+            warn_slowest_query_ms: None,
+            warn_dominant_key_fraction: None,
+            warn_dominant_key_min_ms: Some(50),
+            slowest_key_count: 1,
+            collection_counts_header: 0,
+            analyze_only_above_ms: None,
+            min_total_for_alert: 0,
+
+            escalate_after: 1,
+            escalate_window_ms: 300_000,
 
             of_mode: true,
             n_plus_one_min_count: 5,
             n_plus_one_min_total_ms: Some(5),
+            n_plus_one_rank_by: NPlusOneRank::Count,
+
+            record_query_order: false,
+            query_order_max_events: 500,
+            max_distinct_keys: 10_000,
+            max_header_value_len: 256,
+
+            expected_queries_per_request: None,
+
+            path_normalizer: None,
+
+            skip_paths: Vec::new(),
+            skip_methods: Vec::new(),
+
+            sample_rate: 1.0,
+
+            emit_request_summary: false,
+            request_span: false,
+
+            on_request_complete: None,
+            summary_sink: None,
+        }
+    }
+}
+
+impl MoniOFConfig {
+    /// Cumulative DB latency threshold actually applied.
+    ///
+    /// Precedence: `warn_total_db_latency_ms` if set, else
+    /// `global.slow_db_threshold_ms × expected_queries_per_request` if both
+    /// are set, else none.
+    pub fn effective_warn_total_db_latency_ms(&self, global: &MoniOFGlobalConfig) -> Option<u128> {
+        self.warn_total_db_latency_ms.or_else(|| {
+            let slow = global.slow_db_threshold_ms?;
+            let n = self.expected_queries_per_request?;
+            Some(slow as u128 * n as u128)
+        })
+    }
+
+    /// Check values the builder would reject, for configs built as struct
+    /// literals or deserialized.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if !(0.0..=1.0).contains(&self.sample_rate) {
+            return Err(ConfigError::SampleRateOutOfRange);
+        }
+        if self.warn_dominant_key_fraction.is_some_and(|f| !(f > 0.0 && f <= 1.0)) {
+            return Err(ConfigError::DominantKeyFractionOutOfRange);
         }
+        Ok(())
+    }
+
+    /// Whether `path` matches one of `skip_paths`.
+    pub fn is_skipped_path(&self, path: &str) -> bool {
+        self.skip_paths.iter().any(|p| match p.strip_suffix('*') {
+            Some(prefix) => path.starts_with(prefix),
+            None => path == p,
+        })
+    }
+
+    /// Whether `method` is one of `skip_methods`.
+    pub fn is_skipped_method(&self, method: &str) -> bool {
+        self.skip_methods.iter().any(|m| m.eq_ignore_ascii_case(method))
+    }
+
+    /// Chainable alternative to struct-literal construction:
+    /// `MoniOFConfig::builder().max_total(100).of_mode(true).build()`.
+    pub fn builder() -> MoniOFConfigBuilder {
+        MoniOFConfigBuilder::default()
+    }
+}
+
+/// Builder for [`MoniOFConfig`]; unset fields keep their defaults.
+#[derive(Clone, Debug, Default)]
+pub struct MoniOFConfigBuilder {
+    cfg: MoniOFConfig,
+}
+
+impl MoniOFConfigBuilder {
+    pub fn max_total(mut self, n: usize) -> Self {
+        self.cfg.max_total = n;
+        self
+    }
+
+    pub fn max_same_key(mut self, n: usize) -> Self {
+        self.cfg.max_same_key = n;
+        self
+    }
+
+    pub fn add_response_headers(mut self, on: bool) -> Self {
+        self.cfg.add_response_headers = on;
+        self
+    }
+
+    pub fn log_warnings(mut self, on: bool) -> Self {
+        self.cfg.log_warnings = on;
+        self
+    }
+
+    pub fn warn_total_db_latency_ms(mut self, ms: u128) -> Self {
+        self.cfg.warn_total_db_latency_ms = Some(ms);
+        self
+    }
+
+    pub fn warn_low_total_db_latency_ms(mut self, ms: u128) -> Self {
+        self.cfg.warn_low_total_db_latency_ms = Some(ms);
+        self
+    }
+
+    pub fn warn_slowest_query_ms(mut self, ms: u128) -> Self {
+        self.cfg.warn_slowest_query_ms = Some(ms);
+        self
+    }
+
+    /// # Panics
+    /// If `fraction` is NaN or outside (0, 1].
+    pub fn warn_dominant_key_fraction(mut self, fraction: f64) -> Self {
+        assert!(
+            fraction > 0.0 && fraction <= 1.0,
+            "warn_dominant_key_fraction must be in (0, 1], got {fraction}"
+        );
+        self.cfg.warn_dominant_key_fraction = Some(fraction);
+        self
+    }
+
+    pub fn warn_dominant_key_min_ms(mut self, ms: Option<u128>) -> Self {
+        self.cfg.warn_dominant_key_min_ms = ms;
+        self
+    }
+
+    pub fn slowest_key_count(mut self, n: usize) -> Self {
+        self.cfg.slowest_key_count = n;
+        self
+    }
+
+    pub fn collection_counts_header(mut self, n: usize) -> Self {
+        self.cfg.collection_counts_header = n;
+        self
+    }
+
+    pub fn analyze_only_above_ms(mut self, ms: u128) -> Self {
+        self.cfg.analyze_only_above_ms = Some(ms);
+        self
+    }
+
+    pub fn min_total_for_alert(mut self, n: usize) -> Self {
+        self.cfg.min_total_for_alert = n;
+        self
+    }
+
+    /// Page after `n` occurrences of an alert on a route within `window_ms`.
+    pub fn escalate(mut self, n: usize, window_ms: u64) -> Self {
+        self.cfg.escalate_after = n;
+        self.cfg.escalate_window_ms = window_ms;
+        self
+    }
+
+    pub fn of_mode(mut self, on: bool) -> Self {
+        self.cfg.of_mode = on;
+        self
+    }
+
+    pub fn n_plus_one_min_count(mut self, n: usize) -> Self {
+        self.cfg.n_plus_one_min_count = n;
+        self
+    }
+
+    /// `None` disables the latency gate for N+1 suspects.
+    pub fn n_plus_one_min_total_ms(mut self, ms: Option<u128>) -> Self {
+        self.cfg.n_plus_one_min_total_ms = ms;
+        self
+    }
+
+    pub fn n_plus_one_rank_by(mut self, rank: NPlusOneRank) -> Self {
+        self.cfg.n_plus_one_rank_by = rank;
+        self
+    }
+
+    pub fn record_query_order(mut self, on: bool) -> Self {
+        self.cfg.record_query_order = on;
+        self
+    }
+
+    pub fn query_order_max_events(mut self, n: usize) -> Self {
+        self.cfg.query_order_max_events = n;
+        self
+    }
+
+    pub fn max_header_value_len(mut self, n: usize) -> Self {
+        self.cfg.max_header_value_len = n;
+        self
+    }
+
+    pub fn max_distinct_keys(mut self, n: usize) -> Self {
+        self.cfg.max_distinct_keys = n;
+        self
+    }
+
+    pub fn expected_queries_per_request(mut self, n: usize) -> Self {
+        self.cfg.expected_queries_per_request = Some(n);
+        self
+    }
+
+    /// Label unmatched requests with `normalizer(path)`; see
+    /// [`PathNormalizer::ids`] for the built-in rules.
+    pub fn path_normalizer(mut self, normalizer: PathNormalizer) -> Self {
+        self.cfg.path_normalizer = Some(normalizer);
+        self
+    }
+
+    /// Add a path to `skip_paths` (exact, or prefix when ending with `*`).
+    pub fn skip_path(mut self, path: impl Into<String>) -> Self {
+        self.cfg.skip_paths.push(path.into());
+        self
+    }
+
+    /// Add an HTTP method to `skip_methods` (e.g. `"OPTIONS"`).
+    pub fn skip_method(mut self, method: impl Into<String>) -> Self {
+        self.cfg.skip_methods.push(method.into());
+        self
+    }
+
+    /// # Panics
+    /// If `rate` is NaN or outside [0, 1].
+    pub fn sample_rate(mut self, rate: f64) -> Self {
+        assert!((0.0..=1.0).contains(&rate), "sample_rate must be in [0, 1], got {rate}");
+        self.cfg.sample_rate = rate;
+        self
+    }
+
+    pub fn emit_request_summary(mut self, on: bool) -> Self {
+        self.cfg.emit_request_summary = on;
+        self
+    }
+
+    pub fn request_span(mut self, on: bool) -> Self {
+        self.cfg.request_span = on;
+        self
+    }
+
+    pub fn on_request_complete(
+        mut self,
+        f: impl Fn(&RequestSummary) + Send + Sync + 'static,
+    ) -> Self {
+        self.cfg.on_request_complete = Some(RequestHook::new(f));
+        self
+    }
+
+    pub fn summary_sink(mut self, tx: mpsc::Sender<RequestSummary>) -> Self {
+        self.cfg.summary_sink = Some(tx);
+        self
+    }
+
+    pub fn build(self) -> MoniOFConfig {
+        self.cfg
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_path_collapses_numeric_and_uuid_segments() {
+        assert_eq!(
+            normalize_path("/users/123/orders/3f2b8c1e-9d4a-4b7e-8c2f-1a2b3c4d5e6f"),
+            "/users/:id/orders/:id"
+        );
+    }
+
+    #[test]
+    fn normalize_path_collapses_object_ids_but_not_short_hex_words() {
+        assert_eq!(normalize_path("/posts/5f0c9a1b2c3d4e5f6a7b8c9d"), "/posts/:id");
+        assert_eq!(normalize_path("/tags/cafe/feed"), "/tags/cafe/feed");
+    }
+
+    #[test]
+    fn normalize_path_keeps_trailing_slash_and_root() {
+        assert_eq!(normalize_path("/users/42/"), "/users/:id/");
+        assert_eq!(normalize_path("/"), "/");
+        assert_eq!(normalize_path(""), "");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn partial_toml_deserializes_over_defaults() {
+        let cfg: MoniOFConfig = toml::from_str(
+            r#"
+            max_total = 80
+            warn_slowest_query_ms = 250
+            n_plus_one_rank_by = "total_latency"
+            skip_paths = ["/metrics", "/healthz*"]
+            "#,
+        )
+        .unwrap();
+        let default = MoniOFConfig::default();
+
+        assert_eq!(cfg.max_total, 80);
+        assert_eq!(cfg.warn_slowest_query_ms, Some(250));
+        assert_eq!(cfg.n_plus_one_rank_by, NPlusOneRank::TotalLatency);
+        assert!(cfg.is_skipped_path("/healthz/ready"));
+        assert_eq!(cfg.max_same_key, default.max_same_key);
+        assert_eq!(cfg.warn_total_db_latency_ms, default.warn_total_db_latency_ms);
+    }
+
+    #[test]
+    fn validate_rejects_nan_and_out_of_range_sample_rates() {
+        for rate in [f64::NAN, -0.1, 1.5, f64::INFINITY] {
+            let cfg = MoniOFConfig { sample_rate: rate, ..MoniOFConfig::default() };
+            assert_eq!(cfg.validate(), Err(ConfigError::SampleRateOutOfRange), "rate {rate}");
+        }
+        for rate in [0.0, 0.25, 1.0] {
+            assert!(MoniOFConfig::builder().sample_rate(rate).build().validate().is_ok());
+        }
+    }
+
+    #[test]
+    fn validate_rejects_dominant_key_fractions_outside_zero_to_one() {
+        for fraction in [0.0, -0.5, 1.01, f64::NAN] {
+            let cfg = MoniOFConfig { warn_dominant_key_fraction: Some(fraction), ..MoniOFConfig::default() };
+            assert_eq!(cfg.validate(), Err(ConfigError::DominantKeyFractionOutOfRange), "{fraction}");
+        }
+        assert!(MoniOFConfig::builder().warn_dominant_key_fraction(1.0).build().validate().is_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "sample_rate")]
+    fn builder_panics_on_nan_sample_rate() {
+        MoniOFConfig::builder().sample_rate(f64::NAN);
     }
 }
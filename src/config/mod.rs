@@ -1,5 +1,10 @@
 pub mod global;
 pub mod http;
 
-pub use global::{MoniOFGlobalConfig, initiate, global};
-pub use http::MoniOFConfig;
+pub use global::{
+    ConfigError, MoniOFGlobalConfig, MoniOFGlobalConfigBuilder, global, initiate, is_enabled, is_strict, initiate_with_filter, set_global,
+    update_global,
+};
+pub use http::{
+    MoniOFConfig, MoniOFConfigBuilder, NPlusOneRank, PathNormalizer, RequestHook, normalize_path,
+};
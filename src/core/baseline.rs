@@ -0,0 +1,59 @@
+// Rolling per-key latency baselines for adaptive slow-query detection.
+
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+
+/// Latest samples kept per key.
+const WINDOW: usize = 256;
+/// Below this many samples a key has no baseline yet (fixed threshold applies).
+const MIN_SAMPLES: usize = 50;
+/// The percentile is re-sorted every this many samples, not on each one.
+const RECOMPUTE_EVERY: usize = 32;
+/// Keys beyond this are not tracked (they keep using the fixed threshold).
+const MAX_TRACKED_KEYS: usize = 10_000;
+
+#[derive(Default)]
+struct Baseline {
+    /// Ring buffer of the latest `WINDOW` latencies (ms).
+    samples: Vec<u128>,
+    next: usize,
+    since_recompute: usize,
+    p95: Option<u128>,
+}
+
+impl Baseline {
+    fn push(&mut self, ms: u128) {
+        if self.samples.len() < WINDOW {
+            self.samples.push(ms);
+        } else {
+            self.samples[self.next] = ms;
+        }
+        self.next = (self.next + 1) % WINDOW;
+
+        self.since_recompute += 1;
+        if self.samples.len() >= MIN_SAMPLES
+            && (self.p95.is_none() || self.since_recompute >= RECOMPUTE_EVERY)
+        {
+            let mut sorted = self.samples.clone();
+            sorted.sort_unstable();
+            self.p95 = Some(sorted[(sorted.len() * 95 / 100).min(sorted.len() - 1)]);
+            self.since_recompute = 0;
+        }
+    }
+}
+
+static BASELINES: Lazy<DashMap<String, Baseline>> = Lazy::new(DashMap::new);
+
+/// Record `ms` for `key` and return the key's p95 from *before* this sample
+/// (`None` until enough samples were seen).
+pub(crate) fn observe(key: &str, ms: u128) -> Option<u128> {
+    if let Some(mut b) = BASELINES.get_mut(key) {
+        let p95 = b.p95;
+        b.push(ms);
+        return p95;
+    }
+    if BASELINES.len() < MAX_TRACKED_KEYS {
+        BASELINES.entry(key.to_string()).or_default().push(ms);
+    }
+    None
+}
@@ -1,5 +1,15 @@
 pub mod stats;
 pub mod task_ctx;
+#[cfg(feature = "mongodb")]
+pub(crate) mod baseline;
 
-pub use stats::{QueryKind, QueryStats, QueryStatsHandle, normalize_sql};
-pub use task_ctx::{MONIOF_HANDLE, mark, mark_latency};
+pub use stats::{
+    QueryAccess, QueryEvent, QueryKind, QueryRun, QueryStats, QueryStatsHandle, RelaxedLimits,
+    SqlOp,
+    classify_access, classify_sql, normalize_sql, normalize_sql_with, sql_template,
+};
+pub use task_ctx::{
+    InflightToken, MONIOF_HANDLE, QueryTimer, begin_query, current_stats, mark, mark_latency,
+    mark_pool_wait, mark_rows, measure, measure_async, scoped, set_dimension, spawn_tracked,
+    track, with_relaxed_limits,
+};
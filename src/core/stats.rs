@@ -1,60 +1,808 @@
 use ahash::AHashMap;
 use parking_lot::Mutex;
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Instant;
 use time::{Duration, OffsetDateTime};
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QueryKind { Mongo, Sql, Redis, Other }
+
+impl QueryKind {
+    pub const ALL: [QueryKind; 4] = [QueryKind::Mongo, QueryKind::Sql, QueryKind::Redis, QueryKind::Other];
+
+    /// Key prefix and metric label: `mongo`, `sql`, `redis`, `other`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QueryKind::Mongo => "mongo",
+            QueryKind::Sql => "sql",
+            QueryKind::Redis => "redis",
+            QueryKind::Other => "other",
+        }
+    }
+
+    /// Kind of a stats key from its prefix (`"mongo/users/find"` -> `Mongo`).
+    /// Unknown or missing prefixes map to `Other`.
+    pub fn from_key(key: &str) -> QueryKind {
+        match key.split_once('/').map_or("", |(prefix, _)| prefix) {
+            "mongo" => QueryKind::Mongo,
+            "sql" => QueryKind::Sql,
+            "redis" => QueryKind::Redis,
+            _ => QueryKind::Other,
+        }
+    }
+}
+
+/// Statement type of a SQL query (see `classify_sql`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SqlOp { Select, Insert, Update, Delete, Other }
+
+impl SqlOp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SqlOp::Select => "select",
+            SqlOp::Insert => "insert",
+            SqlOp::Update => "update",
+            SqlOp::Delete => "delete",
+            SqlOp::Other => "other",
+        }
+    }
+}
+
+/// Whether a query reads or writes data (see `classify_access`).
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum QueryKind { Mongo, Sql, Other }
+pub enum QueryAccess { Read, Write }
+
+/// One entry of the ordered query log: which key ran, and when
+/// (ms since the request started).
+#[derive(Debug, Clone)]
+pub struct QueryEvent {
+    pub key: String,
+    pub offset_ms: u128,
+}
+
+/// A run of consecutive identical keys in the ordered query log.
+/// `first`/`last` are 1-based positions in the log (inclusive).
+#[derive(Debug, Clone)]
+pub struct QueryRun {
+    pub key: String,
+    pub first: usize,
+    pub last: usize,
+}
+
+impl QueryRun {
+    pub fn count(&self) -> usize {
+        self.last - self.first + 1
+    }
+}
+
+/// Thresholds for queries issued inside `task_ctx::with_relaxed_limits`
+/// (a known-heavy block, e.g. a bulk import). They replace the request's
+/// `MoniOFConfig` values for every key touched in the block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RelaxedLimits {
+    /// Replaces `max_same_key` for the block's keys.
+    pub max_same_key: usize,
+    /// Replaces `n_plus_one_min_count` for the block's keys.
+    pub n_plus_one_min_count: usize,
+    /// Leave the block's queries out of the `max_total` check.
+    pub exempt_from_max_total: bool,
+}
+
+impl RelaxedLimits {
+    /// No same-key or N+1 warnings for the block's keys, and its queries
+    /// don't count toward `max_total`.
+    pub fn suppress() -> Self {
+        Self {
+            max_same_key: usize::MAX,
+            n_plus_one_min_count: usize::MAX,
+            exempt_from_max_total: true,
+        }
+    }
+}
+
+impl Default for RelaxedLimits {
+    fn default() -> Self {
+        Self::suppress()
+    }
+}
 
 #[derive(Debug)]
 pub struct QueryStats {
+    /// Wall-clock start, for display only; see `elapsed` for timing.
     pub started_at: OffsetDateTime,
+    /// Monotonic start, immune to wall-clock steps (NTP corrections).
+    started_mono: Instant,
     pub total: usize,
     pub per_key: AHashMap<String, usize>,
 
     pub total_db_latency_ms: u128,
     pub per_key_latency_ms: AHashMap<String, u128>,
     pub per_key_max_latency_ms: AHashMap<String, u128>,
+
+    /// Rows returned + affected, as reported by the driver (SQL only today).
+    pub total_rows: u64,
+    pub per_key_rows: AHashMap<String, u64>,
+
+    /// Estimated reply size in bytes (Mongo only today).
+    pub total_reply_bytes: u64,
+    pub per_key_reply_bytes: AHashMap<String, u64>,
+
+    /// Ordered query log (only filled when `max_events > 0`).
+    pub events: Vec<QueryEvent>,
+    /// Cap for `events`; anything beyond is dropped silently. 0 = disabled.
+    pub max_events: usize,
+
+    /// Cap on distinct keys tracked; past it, new keys fold into
+    /// `<kind>/<overflow>` (still counted in `total`). 0 = unbounded.
+    pub max_distinct_keys: usize,
+    /// Whether any key was folded into an overflow bucket.
+    pub key_overflow: bool,
+
+    /// Highest number of queries in flight at the same time.
+    pub peak_concurrency: usize,
+
+    /// Latency of the request's first recorded query (ms); it often pays
+    /// connection setup.
+    pub first_query_ms: Option<u128>,
+
+    /// Time spent waiting for a pooled connection (Mongo checkout), ms.
+    /// Not included in `total_db_latency_ms`, which starts after checkout.
+    pub pool_wait_ms: u128,
+
+    /// Labeled non-DB work timed with `measure` (`other/<label>` -> ms).
+    /// Not a query: kept out of `total`, the DB totals and busy time, so
+    /// it stays part of the request's app time.
+    pub work_ms: AHashMap<String, u128>,
+
+    reads: usize,
+    writes: usize,
+    in_flight: usize,
+    /// Wall time with at least one query in flight (closed intervals), and
+    /// when the current busy interval started.
+    db_busy: std::time::Duration,
+    busy_since: Option<Instant>,
+
+    /// Request dimensions set by handlers (`set_dimension`), e.g.
+    /// `("tenant", "42")`. Reported in alerts and optionally as a metric label.
+    pub dimensions: Vec<(String, String)>,
+
+    /// Keys touched inside `with_relaxed_limits` blocks and the limits that
+    /// apply to them (the most permissive, if several blocks touched a key).
+    pub relaxed: AHashMap<String, RelaxedLimits>,
+    /// Queries issued in blocks with `exempt_from_max_total`.
+    pub exempt_total: usize,
+
+    /// Enclosing scope that also receives everything recorded here
+    /// (set for child scopes created by `task_ctx::scoped`).
+    pub(crate) parent: Option<QueryStatsHandle>,
+}
+
+impl Default for QueryStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// `map[key] += by`, allocating the key only the first time it's seen
+/// (this runs for every query).
+fn add<V: std::ops::AddAssign>(map: &mut AHashMap<String, V>, key: &str, by: V) {
+    match map.get_mut(key) {
+        Some(v) => *v += by,
+        None => {
+            map.insert(key.to_string(), by);
+        }
+    }
 }
 
 impl QueryStats {
     pub fn new() -> Self {
         Self {
             started_at: OffsetDateTime::now_utc(),
+            started_mono: Instant::now(),
             total: 0,
             per_key: AHashMap::new(),
             total_db_latency_ms: 0,
             per_key_latency_ms: AHashMap::new(),
             per_key_max_latency_ms: AHashMap::new(),
+            total_rows: 0,
+            per_key_rows: AHashMap::new(),
+            total_reply_bytes: 0,
+            per_key_reply_bytes: AHashMap::new(),
+            events: Vec::new(),
+            max_events: 0,
+            max_distinct_keys: 0,
+            key_overflow: false,
+            peak_concurrency: 0,
+            first_query_ms: None,
+            pool_wait_ms: 0,
+            work_ms: AHashMap::new(),
+            reads: 0,
+            writes: 0,
+            in_flight: 0,
+            db_busy: std::time::Duration::ZERO,
+            busy_since: None,
+            dimensions: Vec::new(),
+            relaxed: AHashMap::new(),
+            exempt_total: 0,
+            parent: None,
         }
     }
 
+    /// Same as `new`, but keeps an ordered log of up to `max_events` queries.
+    pub fn with_event_log(max_events: usize) -> Self {
+        Self {
+            max_events,
+            ..Self::new()
+        }
+    }
+
+    /// `key`, or its kind's overflow bucket once `max_distinct_keys` distinct
+    /// keys are tracked (and `key_overflow` is set). Borrows `key` in the
+    /// common case; the caller warns about the overflow, outside the lock.
+    fn bucket<'k>(&mut self, key: &'k str) -> Cow<'k, str> {
+        if self.max_distinct_keys == 0
+            || self.per_key.len() < self.max_distinct_keys
+            || self.per_key.contains_key(key)
+        {
+            return Cow::Borrowed(key);
+        }
+
+        self.key_overflow = true;
+        Cow::Owned(format!("{}/<overflow>", QueryKind::from_key(key).as_str()))
+    }
+
     pub fn record(&mut self, key: &str) {
+        let key = &*self.bucket(key);
         self.total += 1;
-        *self.per_key.entry(key.to_string()).or_insert(0) += 1;
+        add(&mut self.per_key, key, 1);
+
+        match classify_access(key) {
+            Some(QueryAccess::Read) => self.reads += 1,
+            Some(QueryAccess::Write) => self.writes += 1,
+            None => {}
+        }
+
+        if self.events.len() < self.max_events {
+            let offset_ms = self.elapsed().whole_milliseconds().max(0) as u128;
+            self.events.push(QueryEvent { key: key.to_string(), offset_ms });
+        }
     }
 
     pub fn record_latency(&mut self, key: &str, ms: u128) {
+        let key = &*self.bucket(key);
+        self.first_query_ms.get_or_insert(ms);
         self.total_db_latency_ms += ms;
-        *self.per_key_latency_ms.entry(key.to_string()).or_insert(0) += ms;
-        let e = self.per_key_max_latency_ms.entry(key.to_string()).or_insert(0);
-        if ms > *e { *e = ms; }
+        add(&mut self.per_key_latency_ms, key, ms);
+        match self.per_key_max_latency_ms.get_mut(key) {
+            Some(e) => *e = (*e).max(ms),
+            None => {
+                self.per_key_max_latency_ms.insert(key.to_string(), ms);
+            }
+        }
+    }
+
+    pub fn record_rows(&mut self, key: &str, rows: u64) {
+        let key = &*self.bucket(key);
+        self.total_rows += rows;
+        add(&mut self.per_key_rows, key, rows);
+    }
+
+    pub fn record_reply_bytes(&mut self, key: &str, bytes: u64) {
+        let key = &*self.bucket(key);
+        self.total_reply_bytes += bytes;
+        add(&mut self.per_key_reply_bytes, key, bytes);
+    }
+
+    /// Set (or replace) a request dimension.
+    pub fn set_dimension(&mut self, name: &str, value: &str) {
+        match self.dimensions.iter_mut().find(|(n, _)| n == name) {
+            Some((_, v)) => *v = value.to_string(),
+            None => self.dimensions.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    pub fn dimension(&self, name: &str) -> Option<&str> {
+        self.dimensions.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    /// Apply `limits` to `key` (see `task_ctx::with_relaxed_limits`).
+    pub fn relax(&mut self, key: &str, limits: RelaxedLimits) {
+        let e = self.relaxed.entry(key.to_string()).or_insert(limits);
+        e.max_same_key = e.max_same_key.max(limits.max_same_key);
+        e.n_plus_one_min_count = e.n_plus_one_min_count.max(limits.n_plus_one_min_count);
+        e.exempt_from_max_total |= limits.exempt_from_max_total;
+    }
+
+    /// `max_same_key` for `key`: its relaxed limit, else `default`.
+    pub fn max_same_key_for(&self, key: &str, default: usize) -> usize {
+        self.relaxed.get(key).map_or(default, |l| l.max_same_key)
+    }
+
+    /// `n_plus_one_min_count` for `key`: its relaxed limit, else `default`.
+    pub fn n_plus_one_min_count_for(&self, key: &str, default: usize) -> usize {
+        self.relaxed.get(key).map_or(default, |l| l.n_plus_one_min_count)
+    }
+
+    /// `total` minus queries exempted from the `max_total` check.
+    pub fn total_for_max_total(&self) -> usize {
+        self.total.saturating_sub(self.exempt_total)
+    }
+
+    pub fn record_pool_wait(&mut self, ms: u128) {
+        self.pool_wait_ms += ms;
+    }
+
+    pub fn record_work(&mut self, key: &str, ms: u128) {
+        add(&mut self.work_ms, key, ms);
+    }
+
+    /// Sum of `work_ms`.
+    pub fn total_work_ms(&self) -> u128 {
+        self.work_ms.values().sum()
+    }
+
+    /// Query count per collection/table (`mongo/users/find` -> `users`),
+    /// most queried first. Keys without one (`redis/get`) group under
+    /// their kind.
+    pub fn counts_by_collection(&self) -> Vec<(String, usize)> {
+        let mut by_coll: AHashMap<&str, usize> = AHashMap::new();
+        for (k, count) in &self.per_key {
+            let coll = match k.split_once('/') {
+                Some((_, rest)) => rest.split_once('/').map_or(QueryKind::from_key(k).as_str(), |(c, _)| c),
+                None => QueryKind::Other.as_str(),
+            };
+            *by_coll.entry(coll).or_insert(0) += count;
+        }
+        let mut out: Vec<(String, usize)> =
+            by_coll.into_iter().map(|(c, n)| (c.to_string(), n)).collect();
+        out.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        out
+    }
+
+    /// Key that moved the most rows in this request.
+    pub fn most_rows(&self) -> Option<(&String, &u64)> {
+        self.per_key_rows.iter().filter(|(_, r)| **r > 0).max_by_key(|(_, r)| **r)
+    }
+
+    /// Key whose replies added up to the most bytes in this request.
+    pub fn heaviest_reply(&self) -> Option<(&String, &u64)> {
+        self.per_key_reply_bytes.iter().filter(|(_, b)| **b > 0).max_by_key(|(_, b)| **b)
+    }
+
+    /// Cumulative latency (ms) per query kind, grouped by key prefix.
+    /// Only kinds with at least one query in this request are returned.
+    pub fn latency_by_kind(&self) -> Vec<(QueryKind, u128)> {
+        let mut by_kind: HashMap<QueryKind, u128> = HashMap::new();
+        for k in self.per_key.keys() {
+            *by_kind.entry(QueryKind::from_key(k)).or_insert(0) +=
+                self.per_key_latency_ms.get(k).copied().unwrap_or(0);
+        }
+        QueryKind::ALL
+            .iter()
+            .filter_map(|kind| by_kind.get(kind).map(|ms| (*kind, *ms)))
+            .collect()
+    }
+
+    /// Query count per kind, grouped by key prefix (see
+    /// [`QueryKind::from_key`]). Only kinds with at least one query.
+    pub fn totals_by_kind(&self) -> HashMap<QueryKind, usize> {
+        let mut by_kind = HashMap::new();
+        for (k, count) in &self.per_key {
+            *by_kind.entry(QueryKind::from_key(k)).or_insert(0) += count;
+        }
+        by_kind
     }
 
+    /// A query started; bumps the in-flight count and the peak.
+    pub fn begin_query(&mut self) {
+        if self.in_flight == 0 {
+            self.busy_since = Some(Instant::now());
+        }
+        self.in_flight += 1;
+        if self.in_flight > self.peak_concurrency {
+            self.peak_concurrency = self.in_flight;
+        }
+    }
+
+    /// A query finished (or was abandoned).
+    pub fn end_query(&mut self) {
+        self.in_flight = self.in_flight.saturating_sub(1);
+        if self.in_flight == 0 {
+            if let Some(since) = self.busy_since.take() {
+                self.db_busy += since.elapsed();
+            }
+        }
+    }
+
+    /// Wall time (ms) during which at least one query was in flight: the
+    /// union of query intervals, so overlapping queries count once and app
+    /// time between them not at all. Unlike `total_db_latency_ms` it never
+    /// exceeds `elapsed`. Only queries with start/finish signals count
+    /// (`begin_query`: the Mongo/SQL instrumentation, `track`, `QueryTimer`).
+    pub fn db_busy_ms(&self) -> u128 {
+        let open = self.busy_since.map(|since| since.elapsed()).unwrap_or_default();
+        (self.db_busy + open).as_millis()
+    }
+
+    /// Queries classified as reads (find/count/aggregate/distinct, SELECT).
+    pub fn reads(&self) -> usize {
+        self.reads
+    }
+
+    /// Queries classified as writes (insert/update/delete, non-SELECT DML).
+    pub fn writes(&self) -> usize {
+        self.writes
+    }
+
+    /// Time since these stats were created, on the monotonic clock: never
+    /// negative, even if the system clock steps backward.
     pub fn elapsed(&self) -> Duration {
-        OffsetDateTime::now_utc() - self.started_at
+        Duration::try_from(self.started_mono.elapsed()).unwrap_or(Duration::MAX)
+    }
+
+    /// Collapse the ordered log into runs of consecutive identical keys,
+    /// e.g. "queries 12–61 were all `mongo/users/find`".
+    pub fn event_runs(&self) -> Vec<QueryRun> {
+        let mut runs: Vec<QueryRun> = Vec::new();
+        for (i, ev) in self.events.iter().enumerate() {
+            match runs.last_mut() {
+                Some(run) if run.key == ev.key => run.last = i + 1,
+                _ => runs.push(QueryRun { key: ev.key.clone(), first: i + 1, last: i + 1 }),
+            }
+        }
+        runs
+    }
+
+    /// Longest run of consecutive identical keys in the ordered log.
+    pub fn longest_run(&self) -> Option<QueryRun> {
+        self.event_runs().into_iter().max_by_key(|r| r.count())
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct QueryStatsHandle(pub Arc<Mutex<QueryStats>>);
+
+impl Default for QueryStatsHandle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl QueryStatsHandle {
     pub fn new() -> Self { Self(Arc::new(Mutex::new(QueryStats::new()))) }
+
+    pub fn with_event_log(max_events: usize) -> Self {
+        Self(Arc::new(Mutex::new(QueryStats::with_event_log(max_events))))
+    }
+}
+
+/// Classify a stats key (`mongo/<coll>/<op>`, `sql/<statement>`, `redis/<cmd>`) as read or write.
+/// Returns `None` when the op can't be recognised (e.g. `other/...` keys).
+pub fn classify_access(key: &str) -> Option<QueryAccess> {
+    let (kind, rest) = key.split_once('/')?;
+
+    let op = match kind {
+        // raw statement fallback (`sql/select * from ...`)
+        "sql" if rest.contains(' ') => rest.split_whitespace().next().unwrap_or("").to_lowercase(),
+        _ => rest.rsplit('/').next().unwrap_or("").to_lowercase(),
+    };
+
+    // find_one_and_update & co. start with "find" but modify data
+    if op.contains("and_update")
+        || op.contains("and_delete")
+        || op.contains("and_replace")
+        || op == "findandmodify"
+    {
+        return Some(QueryAccess::Write);
+    }
+
+    const READS: [&str; 8] = ["find", "count", "aggregate", "distinct", "get", "mget", "estimated", "select"];
+    const WRITES: [&str; 10] = ["insert", "update", "delete", "replace", "bulk", "merge", "upsert", "create", "drop", "set"];
+
+    if READS.iter().any(|r| op.starts_with(r)) {
+        Some(QueryAccess::Read)
+    } else if WRITES.iter().any(|w| op.starts_with(w)) {
+        Some(QueryAccess::Write)
+    } else if kind == "sql" && op == "with" {
+        // CTEs are overwhelmingly used for reads
+        Some(QueryAccess::Read)
+    } else {
+        None
+    }
+}
+
+/// Statement type and primary table of a SQL query.
+///
+/// The table is unquoted and stripped of its schema (`"public"."users"` ->
+/// `users`), so SQL keys can take the same `<table>/<op>` shape as Mongo's
+/// `<collection>/<op>`. `None` when no table can be found.
+pub fn classify_sql(sql: &str) -> (SqlOp, Option<String>) {
+    let lowered = sql.to_lowercase();
+    let words: Vec<&str> = lowered.split_whitespace().collect();
+
+    let op = match words.first().copied().unwrap_or("") {
+        "select" => SqlOp::Select,
+        "insert" => SqlOp::Insert,
+        "update" => SqlOp::Update,
+        "delete" => SqlOp::Delete,
+        _ => SqlOp::Other,
+    };
+
+    let after = |kw: &str| words.iter().position(|w| *w == kw).and_then(|i| words.get(i + 1).copied());
+
+    let raw_table = match op {
+        SqlOp::Select | SqlOp::Delete => after("from"),
+        SqlOp::Insert => after("into"),
+        SqlOp::Update => words.get(1).copied(),
+        SqlOp::Other => None,
+    };
+
+    (op, raw_table.and_then(clean_table_name))
+}
+
+/// `"public"."users"(` -> `users`; `None` for subqueries and empty names.
+fn clean_table_name(token: &str) -> Option<String> {
+    if token.starts_with('(') {
+        return None;
+    }
+    let token = token.split('(').next().unwrap_or("");
+    let token = token.trim_end_matches([',', ';', ')']);
+    let last = token.rsplit('.').next().unwrap_or("");
+    let name = last.trim_matches(|c| matches!(c, '"' | '`' | '[' | ']'));
+
+    if name.is_empty() {
+        None
+    } else {
+        Some(name.to_string())
+    }
 }
 
 // SQL normalization helper (used by sqlx layer)
+//
+// Collapses whitespace, replaces numeric / quoted-string literals with `?`,
+// folds `IN (?, ?, ?)` into `IN (?)`, lowercases and finally truncates (to the
+// global `sql_normalize_max_len`), so structurally identical queries map to the
+// same key.
 pub fn normalize_sql(sql: &str) -> String {
-    let mut reduced = sql.split_whitespace().collect::<Vec<_>>().join(" ");
-    if reduced.len() > 200 { reduced.truncate(200); }
-    reduced.to_lowercase()
+    normalize_sql_with(sql, crate::config::global().sql_normalize_max_len)
+}
+
+/// `normalize_sql` with an explicit truncation length.
+pub fn normalize_sql_with(sql: &str, max_len: usize) -> String {
+    let reduced = sql.split_whitespace().collect::<Vec<_>>().join(" ");
+    let mut reduced = collapse_in_lists(&strip_literals(&reduced)).to_lowercase();
+    truncate_on_char_boundary(&mut reduced, max_len);
+    reduced
+}
+
+/// Statement template: `normalize_sql_with` plus bind placeholders
+/// (`$1`, `?`, `:name`, `@p1`) folded into `?`, so executions of the same
+/// parameterized query share one key regardless of how it was logged.
+pub fn sql_template(sql: &str, max_len: usize) -> String {
+    let reduced = sql.split_whitespace().collect::<Vec<_>>().join(" ");
+    let stripped = normalize_placeholders(&strip_literals(&reduced));
+    let mut reduced = collapse_in_lists(&stripped).to_lowercase();
+    truncate_on_char_boundary(&mut reduced, max_len);
+    reduced
+}
+
+/// `$1`, `:name`, `@p1` -> `?` (but not `::type` casts).
+fn normalize_placeholders(sql: &str) -> String {
+    let mut out = String::with_capacity(sql.len());
+    let mut chars = sql.chars().peekable();
+    let mut prev: Option<char> = None;
+
+    while let Some(c) = chars.next() {
+        let next_is_ident = chars.peek().map(|n| n.is_alphanumeric() || *n == '_').unwrap_or(false);
+        let after_ident = prev.map(|p| p.is_alphanumeric() || p == '_' || p == ':').unwrap_or(false);
+
+        let is_placeholder = match c {
+            '$' | '@' => next_is_ident && !after_ident,
+            ':' => next_is_ident && !after_ident && chars.peek() != Some(&':'),
+            _ => false,
+        };
+
+        if is_placeholder {
+            while let Some(n) = chars.peek() {
+                if n.is_alphanumeric() || *n == '_' {
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            out.push('?');
+            prev = Some('?');
+            continue;
+        }
+
+        out.push(c);
+        prev = Some(c);
+    }
+
+    out
+}
+
+/// Replace `'...'` strings and bare numbers with `?`.
+/// Double-quoted identifiers and `$1`-style placeholders are kept as-is.
+fn strip_literals(sql: &str) -> String {
+    let mut out = String::with_capacity(sql.len());
+    let mut chars = sql.chars().peekable();
+    let mut prev: Option<char> = None;
+
+    while let Some(c) = chars.next() {
+        if c == '\'' {
+            // skip until the closing quote ('' is an escaped quote)
+            while let Some(n) = chars.next() {
+                if n == '\'' {
+                    if chars.peek() == Some(&'\'') {
+                        chars.next();
+                        continue;
+                    }
+                    break;
+                }
+            }
+            out.push('?');
+            prev = Some('?');
+            continue;
+        }
+
+        if c == '"' {
+            out.push(c);
+            for n in chars.by_ref() {
+                out.push(n);
+                if n == '"' {
+                    break;
+                }
+            }
+            prev = Some('"');
+            continue;
+        }
+
+        let part_of_ident = prev.map(|p| p.is_alphanumeric() || p == '_' || p == '$').unwrap_or(false);
+        if c.is_ascii_digit() && !part_of_ident {
+            while let Some(n) = chars.peek() {
+                if n.is_ascii_alphanumeric() || *n == '.' {
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            out.push('?');
+            prev = Some('?');
+            continue;
+        }
+
+        out.push(c);
+        prev = Some(c);
+    }
+
+    out
+}
+
+/// Fold `IN (?, ?, ?)` (any case, any spacing) into `IN (?)`.
+fn collapse_in_lists(sql: &str) -> String {
+    let mut out = String::with_capacity(sql.len());
+    let mut rest = sql;
+
+    while let Some(pos) = find_in_keyword(rest) {
+        let (head, tail) = rest.split_at(pos + 2);
+        out.push_str(head);
+
+        let after = tail.trim_start();
+        let Some(list) = after.strip_prefix('(') else {
+            rest = tail;
+            continue;
+        };
+        let Some(end) = list.find(')') else {
+            rest = tail;
+            continue;
+        };
+
+        let items = &list[..end];
+        let only_params = !items.trim().is_empty()
+            && items.split(',').all(|item| item.trim() == "?");
+
+        if only_params {
+            out.push_str(" (?)");
+            rest = &list[end + 1..];
+        } else {
+            rest = tail;
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+/// Byte offset of the next standalone `in` keyword.
+fn find_in_keyword(sql: &str) -> Option<usize> {
+    let bytes = sql.as_bytes();
+    let is_word = |b: u8| b.is_ascii_alphanumeric() || b == b'_';
+
+    (0..bytes.len().saturating_sub(1)).find(|&i| {
+        bytes[i].eq_ignore_ascii_case(&b'i')
+            && bytes[i + 1].eq_ignore_ascii_case(&b'n')
+            && (i == 0 || !is_word(bytes[i - 1]))
+            && bytes.get(i + 2).map(|b| !is_word(*b)).unwrap_or(true)
+    })
+}
+
+fn truncate_on_char_boundary(s: &mut String, max_len: usize) {
+    if s.len() <= max_len {
+        return;
+    }
+    let mut cut = max_len;
+    while !s.is_char_boundary(cut) {
+        cut -= 1;
+    }
+    s.truncate(cut);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_kind_from_key_reads_the_prefix() {
+        assert_eq!(QueryKind::from_key("mongo/users/find"), QueryKind::Mongo);
+        assert_eq!(QueryKind::from_key("sql/select users"), QueryKind::Sql);
+        assert_eq!(QueryKind::from_key("redis/GET"), QueryKind::Redis);
+        assert_eq!(QueryKind::from_key("other/api.example.com/GET"), QueryKind::Other);
+    }
+
+    #[test]
+    fn normalize_sql_collapses_in_lists() {
+        assert_eq!(
+            normalize_sql_with("SELECT * FROM users WHERE id IN (1, 2, 3)", 200),
+            "select * from users where id in (?)"
+        );
+        assert_eq!(
+            normalize_sql_with("select * from users where id in (4,5)", 200),
+            normalize_sql_with("select * from users where id in ('x')", 200)
+        );
+    }
+
+    #[test]
+    fn normalize_sql_strips_string_and_number_literals() {
+        let a = normalize_sql_with("SELECT * FROM users WHERE name = 'ann' AND age > 30", 200);
+        let b = normalize_sql_with("select *  from users\nwhere name = 'O''Brien' and age > 4.5", 200);
+        assert_eq!(a, "select * from users where name = ? and age > ?");
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn normalize_sql_max_len_keeps_long_queries_apart() {
+        let columns = (0..40).map(|i| format!("column_{i}")).collect::<Vec<_>>().join(", ");
+        let a = format!("SELECT {columns} FROM users");
+        let b = format!("SELECT {columns} FROM orders");
+        assert!(a.len() > 200);
+
+        assert_eq!(normalize_sql_with(&a, 200), normalize_sql_with(&b, 200));
+        assert_ne!(normalize_sql_with(&a, 1_000), normalize_sql_with(&b, 1_000));
+    }
+
+    #[test]
+    fn query_kind_from_key_falls_back_to_other() {
+        assert_eq!(QueryKind::from_key(""), QueryKind::Other);
+        assert_eq!(QueryKind::from_key("mongo"), QueryKind::Other);
+        assert_eq!(QueryKind::from_key("cache/x"), QueryKind::Other);
+    }
+
+    #[test]
+    fn keys_past_max_distinct_keys_fold_into_overflow() {
+        let mut stats = QueryStats::new();
+        stats.max_distinct_keys = 2;
+        for key in ["mongo/users/find", "mongo/orders/find", "mongo/users/find", "sql/items/select"] {
+            stats.record(key);
+            stats.record_latency(key, 2);
+        }
+
+        assert!(stats.key_overflow);
+        assert_eq!(stats.total, 4);
+        assert_eq!(stats.per_key.get("mongo/users/find"), Some(&2));
+        assert_eq!(stats.per_key.get("sql/<overflow>"), Some(&1));
+        assert_eq!(stats.per_key_latency_ms.get("sql/<overflow>"), Some(&2));
+    }
 }
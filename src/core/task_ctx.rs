@@ -1,26 +1,328 @@
-use crate::core::stats::{QueryKind, QueryStatsHandle};
+use crate::config::is_strict;
+use crate::core::stats::{QueryKind, QueryStats, QueryStatsHandle, RelaxedLimits};
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Once;
+use std::time::Instant;
 use tokio::task_local;
 
 task_local! {
     pub static MONIOF_HANDLE: QueryStatsHandle;
 }
 
+fn prefixed(kind: QueryKind, key: &str) -> String {
+    format!("{}/{}", kind.as_str(), key)
+}
+
+/// Set once `MOFMongoEvents` has seen a command, i.e. Mongo is already
+/// counted by the event handler.
+pub(crate) static MONGO_EVENTS_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Manually tracking Mongo calls while `MOFMongoEvents` is registered counts
+/// every operation twice (doubling `total` and tripping thresholds). Warn
+/// once. `MONGO_EVENTS_ACTIVE` is process-wide, so this can't tell whether
+/// the two counts actually overlap (e.g. a second client without the
+/// handler) and must not panic.
+fn check_manual_mongo(kind: QueryKind) {
+    if kind == QueryKind::Mongo && MONGO_EVENTS_ACTIVE.load(Ordering::Relaxed) {
+        static WARNED: Once = Once::new();
+        WARNED.call_once(|| {
+            tracing::warn!(
+                target = "moniof",
+                "QueryKind::Mongo tracked manually while MOFMongoEvents is registered; \
+                 Mongo operations are counted twice"
+            );
+        });
+    }
+}
+
+/// Apply `f` to `h` and then to each enclosing parent scope.
+/// Each lock is released before moving to the parent.
+fn for_each_scope(h: &QueryStatsHandle, f: impl Fn(&mut QueryStats)) {
+    let mut next = Some(h.clone());
+    while let Some(cur) = next {
+        let mut stats = cur.0.lock();
+        f(&mut stats);
+        next = stats.parent.clone();
+    }
+}
+
+/// `for_each_scope` for per-key records. A scope that starts folding keys
+/// into its overflow bucket is warned about once, after its lock is released.
+fn record_in_scopes(h: &QueryStatsHandle, key: &str, f: impl Fn(&mut QueryStats)) {
+    let mut next = Some(h.clone());
+    while let Some(cur) = next {
+        let mut stats = cur.0.lock();
+        let overflowed = stats.key_overflow;
+        f(&mut stats);
+        let newly_overflowed = (!overflowed && stats.key_overflow).then_some(stats.max_distinct_keys);
+        next = stats.parent.clone();
+        drop(stats);
+
+        if let Some(max_distinct_keys) = newly_overflowed {
+            tracing::warn!(
+                target = "moniof",
+                max_distinct_keys,
+                key = %key,
+                "too many distinct query keys in one request (is normalization failing?); \
+                 folding the rest into <overflow>"
+            );
+        }
+    }
+}
+
+/// Count one query under `<kind>/<key>` for the current request.
+///
+/// This is the hook the Mongo/SQL instrumentation uses, and it's public so
+/// custom data sources (Redis, calls to other services, ...) can be counted
+/// too. No-op outside a `MoniOF` request scope.
 pub fn mark(kind: QueryKind, key: &str) {
-    let _ = MONIOF_HANDLE.try_with(|h| {
-        let mut stats = h.0.lock();
-        stats.record(&format!("{}/{}",
-            match kind { QueryKind::Mongo => "mongo", QueryKind::Sql => "sql", QueryKind::Other => "other" },
-            key,
-        ));
-    });
+    let key = prefixed(kind, key);
+    let recorded = MONIOF_HANDLE.try_with(|h| record_in_scopes(h, &key, |stats| stats.record(&key)));
+    if recorded.is_err() && is_strict() {
+        report_unscoped(key);
+    }
+}
+
+/// Keys already reported as queried outside a scope (strict mode). Capped,
+/// since keys come from app data and a background loop would otherwise log
+/// on every query.
+static REPORTED_UNSCOPED: Lazy<DashMap<String, ()>> = Lazy::new(DashMap::new);
+const MAX_REPORTED_UNSCOPED: usize = 100;
+
+fn report_unscoped(key: String) {
+    if REPORTED_UNSCOPED.len() >= MAX_REPORTED_UNSCOPED
+        || REPORTED_UNSCOPED.insert(key.clone(), ()).is_some()
+    {
+        return;
+    }
+    tracing::error!(
+        target = "moniof",
+        key = %key,
+        "strict: query outside any moniof scope (no middleware on this route, plain \
+         tokio::spawn?); not counted. Reported once per key"
+    );
 }
 
+/// Record `ms` of latency under `<kind>/<key>` for the current request.
+/// Pair it with a `mark` for the same key. No-op outside a request scope.
 pub fn mark_latency(kind: QueryKind, key: &str, ms: u128) {
-    let _ = MONIOF_HANDLE.try_with(|h| {
-        let mut stats = h.0.lock();
-        stats.record_latency(&format!("{}/{}",
-            match kind { QueryKind::Mongo => "mongo", QueryKind::Sql => "sql", QueryKind::Other => "other" },
-            key,
-        ), ms);
+    let key = prefixed(kind, key);
+    let _ = MONIOF_HANDLE.try_with(|h| record_in_scopes(h, &key, |stats| stats.record_latency(&key, ms)));
+}
+
+/// Record rows returned/affected under `<kind>/<key>`. No-op outside a request scope.
+pub fn mark_rows(kind: QueryKind, key: &str, rows: u64) {
+    let key = prefixed(kind, key);
+    let _ = MONIOF_HANDLE.try_with(|h| record_in_scopes(h, &key, |stats| stats.record_rows(&key, rows)));
+}
+
+/// Record reply bytes under `<kind>/<key>`. No-op outside a request scope.
+pub fn mark_reply_bytes(kind: QueryKind, key: &str, bytes: u64) {
+    let key = prefixed(kind, key);
+    let _ = MONIOF_HANDLE.try_with(|h| record_in_scopes(h, &key, |stats| stats.record_reply_bytes(&key, bytes)));
+}
+
+/// Record time spent waiting for a pooled connection. No-op outside a request scope.
+pub fn mark_pool_wait(ms: u128) {
+    let _ = MONIOF_HANDLE.try_with(|h| for_each_scope(h, |stats| stats.record_pool_wait(ms)));
+}
+
+/// Tag the current request with a dimension (e.g. `set_dimension("tenant", id)`).
+/// Shown in Slack alerts; with `query_count_dimension` set to `name`, also
+/// the label of `moniof_request_queries`. No-op outside a request scope.
+pub fn set_dimension(name: &str, value: &str) {
+    let _ = MONIOF_HANDLE.try_with(|h| for_each_scope(h, |stats| stats.set_dimension(name, value)));
+}
+
+/// Handle to the current request's stats, for reading mid-handler
+/// (e.g. bail out early once `total` gets too high).
+///
+/// Returns `None` outside a request scope. The handle is shared with the
+/// middleware, so treat it as read-only: mutating it skews the final report.
+pub fn current_stats() -> Option<QueryStatsHandle> {
+    MONIOF_HANDLE.try_with(|h| h.clone()).ok()
+}
+
+/// `tokio::spawn` that keeps the current request's stats scope, so queries
+/// issued by the spawned task (e.g. a parallel fan-out joined later) still
+/// count toward the originating request.
+///
+/// The scope is a task-local: a plain `tokio::spawn` starts without it and
+/// the task's queries silently go uncounted. Outside a request scope this is
+/// just `tokio::spawn`.
+pub fn spawn_tracked<F>(fut: F) -> tokio::task::JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    match current_stats() {
+        Some(h) => tokio::spawn(MONIOF_HANDLE.scope(h, fut)),
+        None => tokio::spawn(fut),
+    }
+}
+
+/// Run `fut` in a child stats scope and return its output together with the
+/// queries it issued, e.g. "this batch item issued 8 queries".
+///
+/// Everything recorded in the child is forwarded to the enclosing request,
+/// so request-level totals are unaffected. Outside a request scope the child
+/// simply stands alone.
+pub async fn scoped<F: Future>(fut: F) -> (F::Output, QueryStats) {
+    let child = QueryStatsHandle::new();
+    if let Some(parent) = current_stats() {
+        let mut c = child.0.lock();
+        c.max_distinct_keys = parent.0.lock().max_distinct_keys;
+        c.parent = Some(parent);
+    }
+
+    let out = MONIOF_HANDLE.scope(child.clone(), fut).await;
+
+    // keep the parent link on the child so late-finishing queries still reach it
+    let mut guard = child.0.lock();
+    let parent = guard.parent.take();
+    let stats = std::mem::take(&mut *guard);
+    guard.parent = parent;
+    drop(guard);
+
+    (out, stats)
+}
+
+/// Run a known-heavy block (a legitimate bulk import, ...) under relaxed
+/// thresholds, without raising them for the whole route.
+///
+/// ```ignore
+/// use moniof::core::{with_relaxed_limits, RelaxedLimits};
+///
+/// with_relaxed_limits(RelaxedLimits::suppress(), import_rows(&db, rows)).await;
+/// ```
+///
+/// Queries still count toward the request as usual, but every key touched
+/// in the block gets `limits` in the final analysis (same-key and N+1
+/// warnings), also for that key's queries outside the block. With
+/// `exempt_from_max_total`, the block's queries are left out of the
+/// `max_total` check. Outside a request scope `fut` simply runs.
+pub async fn with_relaxed_limits<F: Future>(limits: RelaxedLimits, fut: F) -> F::Output {
+    let Some(parent) = current_stats() else {
+        return fut.await;
+    };
+
+    let (out, stats) = scoped(fut).await;
+    for_each_scope(&parent, |p| {
+        for key in stats.per_key.keys() {
+            p.relax(key, limits);
+        }
+        if limits.exempt_from_max_total {
+            p.exempt_total += stats.total;
+        }
     });
+    out
+}
+
+/// Time an arbitrary future and record it as one query (`mark` + `mark_latency`).
+///
+/// ```ignore
+/// use moniof::{track, QueryKind};
+///
+/// let value: Option<String> = track(QueryKind::Redis, "get", conn.get(k)).await?;
+/// ```
+///
+/// Outside a request scope the future simply runs untracked.
+///
+/// Don't wrap Mongo driver calls when `MOFMongoEvents` is registered: the
+/// event handler already counts them.
+pub async fn track<F: Future>(kind: QueryKind, key: &str, fut: F) -> F::Output {
+    check_manual_mongo(kind);
+    mark(kind, key);
+    let _token = begin_query();
+    let started = Instant::now();
+    let out = fut.await;
+    mark_latency(kind, key, started.elapsed().as_millis());
+    out
+}
+
+/// Time a synchronous block (template rendering, serialization, ...) and
+/// record it as non-DB work under `other/<label>` (`x-moniof-work-ms`).
+/// It isn't a query: `total`, DB time, busy time and N+1 detection don't
+/// see it. No-op outside a request scope (`f` still runs).
+pub fn measure<T>(label: &str, f: impl FnOnce() -> T) -> T {
+    let started = Instant::now();
+    let out = f();
+    mark_work(label, started.elapsed().as_millis());
+    out
+}
+
+/// Async counterpart of [`measure`].
+pub async fn measure_async<F: Future>(label: &str, fut: F) -> F::Output {
+    let started = Instant::now();
+    let out = fut.await;
+    mark_work(label, started.elapsed().as_millis());
+    out
+}
+
+fn mark_work(label: &str, ms: u128) {
+    let key = prefixed(QueryKind::Other, label);
+    let _ = MONIOF_HANDLE.try_with(|h| for_each_scope(h, |stats| stats.record_work(&key, ms)));
+}
+
+/// RAII timer for operations that aren't a single future (e.g. a cursor
+/// consumed over many lines): `mark`s on `start`, records latency on drop.
+///
+/// ```ignore
+/// let _t = QueryTimer::start(QueryKind::Other, "export/rows");
+/// while let Some(row) = cursor.try_next().await? { /* ... */ }
+/// // latency recorded here
+/// ```
+///
+/// Like `mark`, it's a no-op outside a request scope.
+pub struct QueryTimer {
+    kind: QueryKind,
+    key: String,
+    started: Instant,
+    _token: Option<InflightToken>,
+}
+
+impl QueryTimer {
+    pub fn start(kind: QueryKind, key: &str) -> Self {
+        check_manual_mongo(kind);
+        mark(kind, key);
+        Self {
+            kind,
+            key: key.to_string(),
+            started: Instant::now(),
+            _token: begin_query(),
+        }
+    }
+}
+
+impl Drop for QueryTimer {
+    fn drop(&mut self) {
+        mark_latency(self.kind, &self.key, self.started.elapsed().as_millis());
+    }
+}
+
+/// Start/finish token for concurrency tracking.
+///
+/// Holds the request's handle, so it can be stashed next to an in-flight
+/// query (e.g. in an event map or span extension) and still decrement the
+/// right request when dropped.
+pub struct InflightToken(QueryStatsHandle);
+
+impl Drop for InflightToken {
+    fn drop(&mut self) {
+        for_each_scope(&self.0, QueryStats::end_query);
+    }
+}
+
+/// Signal that a query started. Drop the returned token when it finishes.
+/// Returns `None` outside a request scope.
+pub fn begin_query() -> Option<InflightToken> {
+    MONIOF_HANDLE
+        .try_with(|h| {
+            for_each_scope(h, QueryStats::begin_query);
+            InflightToken(h.clone())
+        })
+        .ok()
 }
@@ -5,3 +5,13 @@ pub mod mongo_events;
 #[cfg(feature = "sqlx")]
 pub mod sql_events;
 
+#[cfg(feature = "sqlx")]
+pub mod sqlx_pool;
+
+
+#[cfg(feature = "redis")]
+pub mod tracked_redis;
+
+
+#[cfg(feature = "reqwest-tracking")]
+pub mod tracked_http;
@@ -6,42 +6,375 @@ use mongodb::event::command::{
     CommandSucceededEvent,
     CommandFailedEvent,
 };
+use mongodb::event::cmap::{
+    CmapEventHandler,
+    ConnectionCheckedOutEvent,
+    ConnectionCheckoutFailedEvent,
+};
+use mongodb::bson::{Bson, Document};
+use mongodb::options::ServerAddress;
 use dashmap::DashMap;
-use once_cell::sync::Lazy;
-use std::time::Instant;
+use once_cell::sync::{Lazy, OnceCell};
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
 
-use crate::config::global;
+use crate::config::{global, is_enabled, MoniOFGlobalConfig};
 use crate::observability::prom;
+use crate::core::baseline;
 use crate::core::stats::QueryKind;
-use crate::core::task_ctx::{mark, mark_latency};
-use crate::observability::slack;
+use crate::core::task_ctx::{
+    MONGO_EVENTS_ACTIVE, begin_query, mark, mark_latency, mark_pool_wait, mark_reply_bytes,
+    InflightToken,
+};
+use crate::observability::alert::{self, AlertMessage};
+
+/// A command that has started but not yet succeeded/failed.
+struct InflightCommand {
+    started_at: Instant,
+    collection: String,
+    op: String,
+    /// Stats key (`<collection>/<op>`, plus the filter shape when enabled).
+    key: String,
+    /// Cursor id for `getMore`, so the mapping can be dropped once exhausted.
+    cursor_id: Option<i64>,
+    /// Retryable-write identity, forgotten once the write succeeds.
+    retry_key: Option<RetryKey>,
+    /// Dropped with the entry, which closes the query for concurrency tracking.
+    _token: Option<InflightToken>,
+}
+
+/// (`lsid.id`, `txnNumber`): identifies one logical retryable write across
+/// its attempts — the driver re-sends the same session id and transaction
+/// number on retry.
+type RetryKey = (Vec<u8>, i64);
+
+/// Retryable writes seen but not yet succeeded, with when they were first
+/// sent. An attempt whose key is already here is a retry.
+static RETRYABLE_WRITES: Lazy<DashMap<RetryKey, Instant>> = Lazy::new(DashMap::new);
+
+/// Upper bound on `RETRYABLE_WRITES`; beyond it, attempts count separately.
+const MAX_TRACKED_RETRYABLE: usize = 10_000;
+
+/// The command's retryable-write identity. `None` for reads, commands
+/// without a session/txnNumber, and statements of multi-document
+/// transactions (`autocommit: false`), which share a txnNumber but aren't
+/// retries of each other. Those count as separate queries.
+fn retry_key(command: &Document) -> Option<RetryKey> {
+    if command.contains_key("autocommit") {
+        return None;
+    }
+    let txn_number = command.get_i64("txnNumber").ok()?;
+    let session_id = command.get_document("lsid").ok()?.get_binary_generic("id").ok()?;
+    Some((session_id.clone(), txn_number))
+}
 
-/// We track mongo commands by (connection, request_id)
-/// and store (started_at, collection, op) as value.
-static INFLIGHT: Lazy<DashMap<(String, i32), (Instant, String, String)>> =
+/// True when `key` was already sent: this attempt is a driver retry.
+fn is_retry(key: &RetryKey) -> bool {
+    if RETRYABLE_WRITES.contains_key(key) {
+        return true;
+    }
+    if RETRYABLE_WRITES.len() < MAX_TRACKED_RETRYABLE {
+        RETRYABLE_WRITES.insert(key.clone(), Instant::now());
+    }
+    false
+}
+
+/// (server address, driver connection id, request_id) — see `inflight_key`.
+type InflightKey = (ServerAddress, u32, i32);
+
+/// We track mongo commands by (connection, request_id).
+static INFLIGHT: Lazy<DashMap<InflightKey, InflightCommand>> =
     Lazy::new(DashMap::new);
 
-// Build a stable key for the inflight map
-fn inflight_key(connection_dbg: &str, request_id: i32) -> (String, i32) {
-    (connection_dbg.to_string(), request_id)
+/// Open cursor id -> collection, so `getMore` can be labeled with the
+/// collection of the `find`/`aggregate` that opened the cursor.
+static CURSORS: Lazy<DashMap<i64, String>> = Lazy::new(DashMap::new);
+
+/// Upper bound on tracked cursors; beyond it new cursors aren't remembered.
+const MAX_TRACKED_CURSORS: usize = 10_000;
+
+/// `<collection>/<op>` pairs that already completed once in this process
+/// (only filled with `track_cold_commands`).
+static WARM: Lazy<DashMap<String, ()>> = Lazy::new(DashMap::new);
+
+/// Upper bound on `WARM`; beyond it, commands are no longer reported as cold.
+const MAX_TRACKED_WARM_KEYS: usize = 10_000;
+
+/// True the first time `collection`/`op` completes in this process: it paid
+/// connection/warmup cost.
+fn first_in_process(collection: &str, op: &str) -> bool {
+    let key = format!("{}/{}", collection, op);
+    if WARM.contains_key(&key) || WARM.len() >= MAX_TRACKED_WARM_KEYS {
+        return false;
+    }
+    WARM.insert(key, ()).is_none()
+}
+
+// Build a stable key for the inflight map, from `ConnectionInfo` parts
+// (the driver doesn't let us build one in tests).
+// `ConnectionInfo::id` is the driver's connection id (unique per server pool),
+// so together with the address and the wire `request_id` it pairs a started
+// event with its succeeded/failed event without relying on Debug output.
+fn inflight_key(address: &ServerAddress, connection_id: u32, request_id: i32) -> InflightKey {
+    (address.clone(), connection_id, request_id)
 }
 
 /// Extract a reasonable (collection, op) from the started event.
 /// Fallbacks are cheap and good enough for observability labels.
-fn extract_collection_op(event: &CommandStartedEvent) -> (String, String) {
-    // Try `collection` field first (not always present).
-    let collection = event
-        .command
-        .get_str("collection")
+///
+/// `op` is the canonical Mongo op name for keys: the driver's command name,
+/// lowercased (`find`, `findandmodify`, ...), never the Rust method name
+/// (`find_one`). Manual `track`/`mark` calls for Mongo should use the same
+/// scheme so their keys line up with these.
+fn extract_collection_op(command_name: &str, command: &Document, db: &str) -> (String, String) {
+    let op = command_name.to_lowercase();
+
+    // getMore: use the collection of the cursor's originating command
+    if let Some(coll) = getmore_cursor_id(command_name, command).and_then(|id| CURSORS.get(&id)) {
+        return (coll.clone(), op);
+    }
+
+    // Mongo puts the collection under the command-name key itself,
+    // e.g. `{ find: "users", filter: {...} }`.
+    let collection = command
+        .get_str(command_name)
+        .or_else(|_| command.get_str("collection"))
         .ok()
         .map(|s| s.to_string())
         // fallback to db name if no explicit collection
-        .unwrap_or_else(|| event.db.clone());
+        .unwrap_or_else(|| db.to_string());
 
-    let op = event.command_name.to_lowercase();
     (collection, op)
 }
 
+/// The command's filter document: `filter` (find), `query` (count,
+/// distinct, findAndModify), the first statement's `q` (update/delete), or
+/// a leading `$match` stage (aggregate).
+fn command_filter(command: &Document) -> Option<&Document> {
+    if let Ok(f) = command.get_document("filter") {
+        return Some(f);
+    }
+    if let Ok(q) = command.get_document("query") {
+        return Some(q);
+    }
+    for stmts in ["updates", "deletes"] {
+        if let Ok(arr) = command.get_array(stmts) {
+            return arr.first()?.as_document()?.get_document("q").ok();
+        }
+    }
+    let first_stage = command.get_array("pipeline").ok()?.first()?.as_document()?;
+    first_stage.get_document("$match").ok()
+}
+
+/// `{a,b}`: sorted top-level filter field names (never values), when
+/// `mongo_key_include_filter_fields` is on and the command has a filter.
+fn filter_shape(command: &Document, cfg: &MoniOFGlobalConfig) -> Option<String> {
+    if !cfg.mongo_key_include_filter_fields {
+        return None;
+    }
+    let filter = command_filter(command)?;
+    let mut fields: Vec<&str> = filter.keys().map(String::as_str).collect();
+    fields.sort_unstable();
+    Some(format!("{{{}}}", fields.join(",")))
+}
+
+/// Full match of `name` against a `collection_label_rewrite` pattern:
+/// `*` matches any run of characters, `#` exactly one ASCII digit.
+///
+/// Iterative: on a mismatch, backtrack to the last `*` and let it swallow
+/// one more byte. Worst case is O(pattern × name), not exponential in the
+/// number of stars.
+fn pattern_matches(pattern: &[u8], name: &[u8]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // (index of the last `*` seen, name index it currently matches up to)
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(b'#') if name[n].is_ascii_digit() => {
+                p += 1;
+                n += 1;
+            }
+            Some(&c) if c != b'#' && c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((sp, sn)) => {
+                    star = Some((sp, sn + 1));
+                    p = sp + 1;
+                    n = sn + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// Metric label for `collection`: the first matching `collection_label_rewrite`
+/// rule's label, else the name itself. Stats keys keep the real name.
+fn collection_label<'a>(collection: &'a str, cfg: &'a MoniOFGlobalConfig) -> &'a str {
+    cfg.collection_label_rewrite
+        .iter()
+        .find(|(pattern, _)| pattern_matches(pattern.as_bytes(), collection.as_bytes()))
+        .map_or(collection, |(_, label)| label.as_str())
+}
+
+fn getmore_cursor_id(command_name: &str, command: &Document) -> Option<i64> {
+    if command_name != "getMore" {
+        return None;
+    }
+    command.get_i64("getMore").ok()
+}
+
+/// Remember / forget cursor -> collection mappings based on a successful reply.
+fn update_cursor_map(event: &CommandSucceededEvent, collection: &str, op: &str, cursor_id: Option<i64>) {
+    let reply_id = event
+        .reply
+        .get_document("cursor")
+        .ok()
+        .and_then(|c| c.get_i64("id").ok());
+
+    match op {
+        "find" | "aggregate" => {
+            if let Some(id) = reply_id.filter(|id| *id != 0) {
+                if CURSORS.len() < MAX_TRACKED_CURSORS {
+                    CURSORS.insert(id, collection.to_string());
+                }
+            }
+        }
+        // an id of 0 in the reply means the cursor is exhausted
+        "getmore" => {
+            if let (Some(id), Some(0)) = (cursor_id, reply_id) {
+                CURSORS.remove(&id);
+            }
+        }
+        "killcursors" => {
+            if let Ok(ids) = event.reply.get_array("cursorsKilled") {
+                for id in ids.iter().filter_map(|b| b.as_i64()) {
+                    CURSORS.remove(&id);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Serialized size of `reply`, or `None` when it isn't available (the
+/// driver blanks replies of sensitive commands such as `saslStart`).
+/// Counted through a sink, so a large `find` batch isn't copied per event.
+fn reply_bytes(reply: &Document) -> Option<u64> {
+    if reply.is_empty() {
+        return None;
+    }
+    let mut counter = ByteCounter(0);
+    reply.to_writer(&mut counter).ok()?;
+    Some(counter.0)
+}
+
+/// `io::Write` that only counts what's written.
+struct ByteCounter(u64);
+
+impl std::io::Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Copy of `doc` with the values of any field named in `fields` replaced by
+/// `"<redacted>"`, recursing into sub-documents and arrays.
+pub fn redact_document(doc: &Document, fields: &[String]) -> Document {
+    doc.iter()
+        .map(|(k, v)| {
+            let v = if fields.iter().any(|f| f.eq_ignore_ascii_case(k)) {
+                Bson::String("<redacted>".to_string())
+            } else {
+                redact_bson(v, fields)
+            };
+            (k.clone(), v)
+        })
+        .collect()
+}
+
+fn redact_bson(value: &Bson, fields: &[String]) -> Bson {
+    match value {
+        Bson::Document(d) => Bson::Document(redact_document(d, fields)),
+        Bson::Array(a) => Bson::Array(a.iter().map(|v| redact_bson(v, fields)).collect()),
+        other => other.clone(),
+    }
+}
+
+/// Remove INFLIGHT entries older than `max_age` (commands that never got a
+/// succeeded/failed event). Returns how many were reclaimed.
+pub(crate) fn sweep_inflight(max_age: Duration) -> usize {
+    let before = INFLIGHT.len();
+    INFLIGHT.retain(|_, cmd| cmd.started_at.elapsed() < max_age);
+    // writes that failed for good leave their retry key behind
+    RETRYABLE_WRITES.retain(|_, first_sent| first_sent.elapsed() < max_age);
+    before.saturating_sub(INFLIGHT.len())
+}
+
+/// Start the background INFLIGHT sweeper (once per process).
+///
+/// Runs on a plain thread so `initiate` can be called before any runtime
+/// exists. The max age is re-read from the global config on every pass.
+pub(crate) fn start_inflight_sweeper() {
+    static STARTED: OnceCell<()> = OnceCell::new();
+    if STARTED.set(()).is_err() {
+        return;
+    }
+
+    let spawned = std::thread::Builder::new()
+        .name("moniof-inflight-sweep".to_string())
+        .spawn(|| loop {
+            let max_age_ms = global().inflight_max_age_ms;
+            // sweep at half the max age, between 1s and 60s
+            let every_ms = max_age_ms.map(|ms| ms / 2).unwrap_or(60_000).clamp(1_000, 60_000);
+            std::thread::sleep(Duration::from_millis(every_ms));
+
+            if let Some(ms) = max_age_ms {
+                let reclaimed = sweep_inflight(Duration::from_millis(ms));
+                if reclaimed > 0 {
+                    tracing::debug!(
+                        target = "MoniOF::mongo",
+                        reclaimed,
+                        max_age_ms = ms,
+                        "reclaimed stale in-flight mongo commands"
+                    );
+                }
+            }
+        });
+
+    if let Err(e) = spawned {
+        tracing::warn!(target = "MoniOF::mongo", "failed to start inflight sweeper: {}", e);
+    }
+}
+
+/// Remove a finished command from INFLIGHT (or a placeholder if it was never seen).
+fn take_inflight(key: &InflightKey, command_name: &str) -> InflightCommand {
+    INFLIGHT
+        .remove(key)
+        .map(|(_, v)| v)
+        .unwrap_or_else(|| InflightCommand {
+            started_at: Instant::now(),
+            collection: "unknown".to_string(),
+            op: command_name.to_lowercase(),
+            key: format!("unknown/{}", command_name.to_lowercase()),
+            cursor_id: None,
+            retry_key: None,
+            _token: None,
+        })
+}
+
 /// Main MongoDB CommandEventHandler used by moniof.
 ///
 /// Attach this handler to ClientOptions::command_event_handler to let moniof:
@@ -49,56 +382,147 @@ fn extract_collection_op(event: &CommandStartedEvent) -> (String, String) {
 /// - measure per-command latency,
 /// - update per-request DB totals,
 /// - emit logs & Slack alerts for slow/failed ops.
+///
+/// Also attach it to `ClientOptions::cmap_event_handler` to measure time
+/// spent waiting for a pooled connection (before the command starts).
+///
+/// Retried writes (retryable writes) count as one query: attempts carrying
+/// the same `lsid.id` + `txnNumber` as an earlier, not yet successful one
+/// are only counted in `moniof_mongo_retries_total` (their latency still
+/// adds up). Commands without both fields count every attempt.
 #[derive(Default, Debug)]
 pub struct MOFMongoEvents;
 
+// Checkout events fire on the task running the operation, so the wait lands
+// in that request's stats. They carry no collection: the command isn't known
+// until after checkout.
+impl CmapEventHandler for MOFMongoEvents {
+    fn handle_connection_checked_out_event(&self, event: ConnectionCheckedOutEvent) {
+        if !is_enabled() {
+            return;
+        }
+        mark_pool_wait(event.duration.as_millis());
+        prom::observe_mongo_pool_wait("checked_out", event.duration.as_secs_f64());
+    }
+
+    fn handle_connection_checkout_failed_event(&self, event: ConnectionCheckoutFailedEvent) {
+        if !is_enabled() {
+            return;
+        }
+        mark_pool_wait(event.duration.as_millis());
+        prom::observe_mongo_pool_wait("failed", event.duration.as_secs_f64());
+
+        tracing::warn!(
+            target = "moniof::mongo",
+            address = %event.address,
+            reason = ?event.reason,
+            wait_ms = %event.duration.as_millis(),
+            "Mongo connection checkout failed (pool exhausted?)"
+        );
+    }
+}
+
 impl CommandEventHandler for MOFMongoEvents {
     fn handle_command_started_event(&self, event: CommandStartedEvent) {
+        if !is_enabled() {
+            return;
+        }
+        MONGO_EVENTS_ACTIVE.store(true, Ordering::Relaxed);
         let cfg = global();
 
-        let connection_dbg = format!("{:?}", event.connection);
-        let key_inflight = inflight_key(&connection_dbg, event.request_id);
+        let key_inflight = inflight_key(&event.connection.address, event.connection.id, event.request_id);
         let started_at = Instant::now();
 
-        let (collection, op) = extract_collection_op(&event);
-        let logical_key = format!("{}/{}", collection, op);
+        let (collection, op) = extract_collection_op(&event.command_name, &event.command, &event.db);
+        let logical_key = match filter_shape(&event.command, &cfg) {
+            Some(shape) => format!("{}/{}{}", collection, op, shape),
+            None => format!("{}/{}", collection, op),
+        };
+
+        let retry_key = retry_key(&event.command);
+        let retry = retry_key.as_ref().is_some_and(is_retry);
 
         // Track this command in our inflight map
-        INFLIGHT.insert(key_inflight, (started_at, collection.clone(), op.clone()));
+        INFLIGHT.insert(
+            key_inflight,
+            InflightCommand {
+                started_at,
+                collection: collection.clone(),
+                op: op.clone(),
+                key: logical_key.clone(),
+                cursor_id: getmore_cursor_id(&event.command_name, &event.command),
+                retry_key,
+                _token: begin_query(),
+            },
+        );
 
-        // Count query immediately
-        mark(QueryKind::Mongo, &logical_key);
+        // Count query immediately; a retry is the same logical write
+        if retry {
+            prom::inc_mongo_retry(collection_label(&collection, &cfg), &op);
+        } else {
+            mark(QueryKind::Mongo, &logical_key);
+        }
 
         if cfg.log_each_db_event {
-            tracing::debug!(
-                target = "MoniOF::mongo",
-                db = %event.db,
-                command = %event.command_name,
-                key = %logical_key,
-                "mongo started"
-            );
+            if cfg.log_db_command_body {
+                // never log a command body without redacting it first
+                let body = redact_document(&event.command, &cfg.redact_fields);
+                tracing::debug!(
+                    target = "MoniOF::mongo",
+                    db = %event.db,
+                    command = %event.command_name,
+                    key = %logical_key,
+                    body = %body,
+                    "mongo started"
+                );
+            } else {
+                tracing::debug!(
+                    target = "MoniOF::mongo",
+                    db = %event.db,
+                    command = %event.command_name,
+                    key = %logical_key,
+                    "mongo started"
+                );
+            }
         }
     }
 
     fn handle_command_succeeded_event(&self, event: CommandSucceededEvent) {
+        let key_inflight = inflight_key(&event.connection.address, event.connection.id, event.request_id);
+        if !is_enabled() {
+            // may have started before the switch flipped
+            INFLIGHT.remove(&key_inflight);
+            return;
+        }
         let cfg = global();
 
-        let connection_dbg = format!("{:?}", event.connection);
-        let key_inflight = inflight_key(&connection_dbg, event.request_id);
+        let cmd = take_inflight(&key_inflight, &event.command_name);
+        let (collection, op) = (cmd.collection.as_str(), cmd.op.as_str());
 
-        let (started_at, collection, op) = INFLIGHT
-            .remove(&key_inflight)
-            .map(|(_, v)| v)
-            .unwrap_or_else(|| (Instant::now(), "unknown".to_string(), event.command_name.to_lowercase()));
+        let ms = cmd.started_at.elapsed().as_millis();
+        let logical_key = cmd.key.clone();
 
-        let ms = started_at.elapsed().as_millis();
-        let logical_key = format!("{}/{}", collection, op);
+        update_cursor_map(&event, collection, op, cmd.cursor_id);
+        if let Some(ref key) = cmd.retry_key {
+            RETRYABLE_WRITES.remove(key);
+        }
 
         // Record latency
         mark_latency(QueryKind::Mongo, &logical_key, ms);
 
-        // Prometheus observation
-        prom::observe_mongo_cmd(&collection, &op, (ms as f64) / 1000.0);
+        // Prometheus observation; warmup kept out of the main histogram
+        let label = collection_label(collection, &cfg);
+        if cfg.track_cold_commands && first_in_process(collection, op) {
+            prom::observe_mongo_cold_cmd(label, op, (ms as f64) / 1000.0);
+        } else {
+            prom::observe_mongo_cmd(label, op, (ms as f64) / 1000.0);
+        }
+
+        // big documents cost bandwidth and memory even when they're fast
+        if let Some(bytes) = reply_bytes(&event.reply) {
+            mark_reply_bytes(QueryKind::Mongo, &logical_key, bytes);
+            prom::observe_mongo_reply_bytes(label, op, bytes);
+        }
 
         if cfg.log_each_db_event {
             tracing::info!(
@@ -109,21 +533,30 @@ impl CommandEventHandler for MOFMongoEvents {
             );
         }
 
-        if let Some(th) = cfg.slow_db_threshold_ms {
-            if ms >= th as u128 {
+        // adaptive: slower than this (collection, op)'s own p95 × factor;
+        // keys without enough history yet use the fixed threshold
+        let adaptive = cfg.adaptive_slow_factor.and_then(|factor| {
+            baseline::observe(&logical_key, ms)
+                .map(|p95| ((p95.max(1) as f64) * factor).ceil() as u128)
+        });
+        let slow_threshold = adaptive.or(cfg.slow_db_threshold_ms.map(u128::from));
+
+        if let Some(th) = slow_threshold {
+            if ms >= th {
                 tracing::warn!(
                     target = "MoniOF::mongo",
                     key = %logical_key,
                     latency_ms = %ms,
-                    threshold_ms = th,
+                    threshold_ms = %th,
+                    adaptive = adaptive.is_some(),
                     "slow mongo command"
                 );
-                if let Some(ref hook) = cfg.slack_webhook {
+                if alert::has_sink(&cfg) {
                     let text = format!(
                         "🐢 *Slow MongoDB command*\n• `key`: `{}`\n• `latency`: {} ms",
                         logical_key, ms
                     );
-                    tokio::spawn(slack::notify(Some(hook.clone()), text));
+                    alert::dispatch(&cfg, AlertMessage { kinds: vec!["slow_mongo"], text });
                 }
             }
         }
@@ -142,21 +575,26 @@ impl CommandEventHandler for MOFMongoEvents {
     }
 
     fn handle_command_failed_event(&self, event: CommandFailedEvent) {
+        let key_inflight = inflight_key(&event.connection.address, event.connection.id, event.request_id);
+        if !is_enabled() {
+            INFLIGHT.remove(&key_inflight);
+            return;
+        }
         let cfg = global();
 
-        let connection_dbg = format!("{:?}", event.connection);
-        let key_inflight = inflight_key(&connection_dbg, event.request_id);
+        let cmd = take_inflight(&key_inflight, &event.command_name);
+        let (collection, op) = (cmd.collection.as_str(), cmd.op.as_str());
 
-        let (started_at, collection, op) = INFLIGHT
-            .remove(&key_inflight)
-            .map(|(_, v)| v)
-            .unwrap_or_else(|| (Instant::now(), "unknown".to_string(), event.command_name.to_lowercase()));
+        let ms = cmd.started_at.elapsed().as_millis();
+        let logical_key = cmd.key.clone();
 
-        let ms = started_at.elapsed().as_millis();
-        let logical_key = format!("{}/{}", collection, op);
+        // a failed getMore leaves the cursor unusable
+        if let Some(id) = cmd.cursor_id {
+            CURSORS.remove(&id);
+        }
 
         mark_latency(QueryKind::Mongo, &logical_key, ms);
-        prom::observe_mongo_cmd(&collection, &op, (ms as f64) / 1000.0);
+        prom::observe_mongo_cmd(collection_label(collection, &cfg), op, (ms as f64) / 1000.0);
 
         tracing::warn!(
             target = "MoniOF::mongo",
@@ -165,12 +603,119 @@ impl CommandEventHandler for MOFMongoEvents {
             "mongo failed"
         );
 
-        if let Some(ref hook) = cfg.slack_webhook {
+        if alert::has_sink(&cfg) {
             let text = format!(
                 "❌ *MongoDB command failed*\n• `key`: `{}`\n• `latency`: {} ms",
                 logical_key, ms
             );
-            tokio::spawn(slack::notify(Some(hook.clone()), text));
+            alert::dispatch(&cfg, AlertMessage { kinds: vec!["mongo_failed"], text });
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mongodb::bson::doc;
+
+    #[test]
+    fn collection_comes_from_the_command_name_key() {
+        let find = doc! { "find": "users", "filter": { "_id": 1 }, "$db": "app" };
+        assert_eq!(
+            extract_collection_op("find", &find, "app"),
+            ("users".to_string(), "find".to_string())
+        );
+
+        let fam = doc! { "findAndModify": "orders", "query": { "_id": 1 } };
+        assert_eq!(
+            extract_collection_op("findAndModify", &fam, "app"),
+            ("orders".to_string(), "findandmodify".to_string())
+        );
+    }
+
+    #[test]
+    fn collection_falls_back_to_the_db_name() {
+        let ping = doc! { "ping": 1 };
+        assert_eq!(
+            extract_collection_op("ping", &ping, "admin"),
+            ("admin".to_string(), "ping".to_string())
+        );
+    }
+
+    #[test]
+    fn filter_shape_separates_different_filter_fields() {
+        let cfg = MoniOFGlobalConfig::builder().mongo_key_include_filter_fields(true).build().unwrap();
+        let by_id = doc! { "find": "users", "filter": { "_id": 1 } };
+        let by_email = doc! { "find": "users", "filter": { "email": "a@b.c", "active": true } };
+
+        assert_eq!(filter_shape(&by_id, &cfg).as_deref(), Some("{_id}"));
+        // sorted field names, never values
+        assert_eq!(filter_shape(&by_email, &cfg).as_deref(), Some("{active,email}"));
+        assert_eq!(filter_shape(&by_id, &MoniOFGlobalConfig::default()), None);
+    }
+
+    #[test]
+    fn sharded_collections_share_one_label() {
+        let cfg = MoniOFGlobalConfig::builder()
+            .collection_label_rewrite("events_####_##", "events")
+            .build()
+            .unwrap();
+        assert_eq!(collection_label("events_2024_01", &cfg), "events");
+        assert_eq!(collection_label("events_2024_02", &cfg), "events");
+        assert_eq!(collection_label("events_archive", &cfg), "events_archive");
+    }
+
+    #[test]
+    fn pattern_matches_globs() {
+        let m = |p: &str, n: &str| pattern_matches(p.as_bytes(), n.as_bytes());
+        assert!(m("logs_*", "logs_"));
+        assert!(m("logs_*", "logs_eu_2024"));
+        assert!(m("*_v#", "users_v2"));
+        assert!(m("a*b*c", "a_b_b_c"));
+        assert!(m("*", ""));
+        assert!(!m("logs_*", "log"));
+        assert!(!m("*_v#", "users_vx"));
+        assert!(!m("a*b*c", "a_b_b_"));
+        // `#` is never a literal
+        assert!(!m("v#", "v#"));
+        // many stars against a long non-match stays fast
+        assert!(!m(&"*a".repeat(30), &"a".repeat(29)));
+    }
+
+    #[test]
+    fn reply_bytes_counts_the_bson_size_and_skips_blank_replies() {
+        let reply = doc! { "ok": 1.0, "cursor": { "id": 0_i64, "firstBatch": [{ "name": "ada" }] } };
+        let mut buf = Vec::new();
+        reply.to_writer(&mut buf).unwrap();
+        assert_eq!(reply_bytes(&reply), Some(buf.len() as u64));
+
+        // sensitive commands' replies are blanked by the driver
+        assert_eq!(reply_bytes(&Document::new()), None);
+    }
+
+    #[test]
+    fn finished_event_finds_the_started_command() {
+        let started_addr = ServerAddress::parse("db1:27017").unwrap();
+        INFLIGHT.insert(
+            inflight_key(&started_addr, 3, 9_001),
+            InflightCommand {
+                started_at: Instant::now(),
+                collection: "users".to_string(),
+                op: "find".to_string(),
+                key: "users/find".to_string(),
+                cursor_id: None,
+                retry_key: None,
+                _token: None,
+            },
+        );
+
+        // the succeeded event carries its own copy of the connection info
+        let finished_addr = ServerAddress::parse("db1:27017").unwrap();
+        let cmd = take_inflight(&inflight_key(&finished_addr, 3, 9_001), "find");
+        assert_eq!((cmd.collection.as_str(), cmd.key.as_str()), ("users", "users/find"));
+
+        // consumed: a second lookup only gets the placeholder
+        let again = take_inflight(&inflight_key(&finished_addr, 3, 9_001), "find");
+        assert_eq!(again.key, "unknown/find");
+    }
+}
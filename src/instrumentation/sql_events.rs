@@ -1,29 +1,66 @@
 // src/instrumentation/sql_events.rs
 #![cfg(feature = "sqlx")]
 
-use crate::core::stats::{normalize_sql, QueryKind};
-use crate::core::task_ctx::{mark, mark_latency};
+use crate::config::{global, is_enabled};
+use crate::core::stats::{classify_sql, normalize_sql, sql_template, QueryKind};
+use crate::observability::alert::{self, AlertMessage};
+use crate::observability::prom;
+use crate::core::task_ctx::{begin_query, mark, mark_latency, mark_rows, InflightToken};
 
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
 use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Instant;
 
-use tracing::{span::Attributes, Event, Id, Subscriber};
+use tracing::{span::{Attributes, Record}, Event, Id, Subscriber};
 use tracing_subscriber::{layer::Context, Layer};
 
+/// Mirror of `sql_targets`, checked for every span/event without cloning
+/// the global config.
+static SQL_TARGETS: Lazy<RwLock<Vec<String>>> =
+    Lazy::new(|| RwLock::new(vec!["sqlx::query".to_string()]));
+
+/// Apply `sql_targets` (called whenever the global config changes).
+pub(crate) fn configure_targets(targets: &[String]) {
+    *SQL_TARGETS.write() = targets.to_vec();
+}
+
+fn is_sql_target(target: &str) -> bool {
+    SQL_TARGETS.read().iter().any(|p| target.starts_with(p.as_str()))
+}
+
+/// Set once the layer is part of a dispatcher (see strict mode).
+pub(crate) static SQL_LAYER_REGISTERED: AtomicBool = AtomicBool::new(false);
+
 /// Internal storage for SQL spans.
 struct SqlSpanData {
     key: String,
+    table: String,
+    op: String,
     started_at: Instant,
+    /// Dropped with the span data on close (concurrency tracking).
+    _token: Option<InflightToken>,
+    /// Error recorded on the span (at creation or later via `record`).
+    error: Option<String>,
+    /// `rows_returned` + `rows_affected`, if the span reported them.
+    rows: Option<u64>,
 }
 
-/// Visitor that extracts SQL from span attributes.
+/// Visitor that extracts SQL (and any error) from span attributes.
 struct SqlVisitor {
     sql: Option<String>,
+    error: Option<String>,
+    rows: Option<u64>,
 }
 
 impl SqlVisitor {
     fn new() -> Self {
-        Self { sql: None }
+        Self { sql: None, error: None, rows: None }
+    }
+
+    fn is_error_field(name: &str) -> bool {
+        matches!(name, "error" | "error.message" | "exception.message")
     }
 }
 
@@ -33,18 +70,87 @@ impl tracing::field::Visit for SqlVisitor {
             "db.statement" | "statement" | "message" => {
                 self.sql = Some(value.to_string());
             }
+            "otel.status_code" if value.eq_ignore_ascii_case("error") => {
+                self.error.get_or_insert_with(|| "error".to_string());
+            }
+            name if Self::is_error_field(name) && !value.is_empty() => {
+                self.error = Some(value.to_string());
+            }
             _ => {}
         }
     }
 
+    fn record_u64(&mut self, field: &tracing::field::Field, value: u64) {
+        if matches!(field.name(), "rows_returned" | "rows_affected") {
+            *self.rows.get_or_insert(0) += value;
+        }
+    }
+
+    fn record_i64(&mut self, field: &tracing::field::Field, value: i64) {
+        self.record_u64(field, value.max(0) as u64);
+    }
+
+    fn record_error(&mut self, field: &tracing::field::Field, value: &(dyn std::error::Error + 'static)) {
+        if Self::is_error_field(field.name()) {
+            self.error = Some(value.to_string());
+        }
+    }
+
     fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn fmt::Debug) {
         if self.sql.is_none() && field.name() == "message" {
             self.sql = Some(format!("{value:?}"));
+        } else if Self::is_error_field(field.name()) {
+            self.error = Some(format!("{value:?}"));
         }
     }
 }
 
+/// Stats key + Prometheus (table, op) labels for a statement.
+///
+/// Keys take the `<table>/<op>` shape (recorded as `sql/users/select`) when
+/// the statement can be classified, else the normalized statement. With
+/// `sql_group_by_template` the key is always the statement template. Labels
+/// fall back to `"unknown"`.
+fn sql_key_labels(raw_sql: &str) -> (String, String, String) {
+    let cfg = global();
+    let (op, table) = classify_sql(raw_sql);
+    let op = op.as_str().to_string();
+
+    if cfg.sql_group_by_template {
+        let key = sql_template(raw_sql, cfg.sql_normalize_max_len);
+        return (key, table.unwrap_or_else(|| "unknown".to_string()), op);
+    }
+
+    match table {
+        Some(table) => (format!("{}/{}", table, op), table, op),
+        None => (normalize_sql(raw_sql), "unknown".to_string(), op),
+    }
+}
+
+/// Failure path shared by spans and events: count, log and alert.
+fn record_failure(key: &str, ms: u128, error: &str) {
+    prom::inc_sql_error();
+
+    tracing::warn!(
+        target = "MoniOF::sql",
+        key = %key,
+        latency_ms = %ms,
+        error = %error,
+        "SQL failed"
+    );
+
+    let cfg = global();
+    if alert::has_sink(&cfg) {
+        let text = format!(
+            "❌ *SQL query failed*\n• `key`: `{}`\n• `latency`: {} ms\n• `error`: {}",
+            key, ms, error
+        );
+        alert::dispatch(&cfg, AlertMessage { kinds: vec!["sql_failed"], text });
+    }
+}
+
 /// SQLx instrumentation layer for moniof
+#[derive(Default)]
 pub struct MOFSqlEvents;
 
 impl MOFSqlEvents {
@@ -53,10 +159,28 @@ impl MOFSqlEvents {
     }
 }
 
+/// The SQL instrumentation layer, for composing into your own subscriber:
+///
+/// ```ignore
+/// tracing_subscriber::registry()
+///     .with(my_fmt_layer)
+///     .with(moniof::moniof_layer())
+///     .init();
+/// ```
+///
+/// SQL queries are only counted when this layer is part of the active subscriber.
+pub fn moniof_layer() -> MOFSqlEvents {
+    MOFSqlEvents::new()
+}
+
 impl<S> Layer<S> for MOFSqlEvents
 where
     S: Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
 {
+    fn on_register_dispatch(&self, _subscriber: &tracing::Dispatch) {
+        SQL_LAYER_REGISTERED.store(true, Ordering::Relaxed);
+    }
+
     // When span is created
     fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
         let span = match ctx.span(id) {
@@ -66,7 +190,7 @@ where
 
         let target = span.metadata().target();
 
-        if !target.starts_with("sqlx::query") {
+        if !is_sql_target(target) || !is_enabled() {
             return;
         }
 
@@ -74,12 +198,17 @@ where
         attrs.record(&mut vis);
 
         let raw_sql = vis.sql.unwrap_or_else(|| target.to_string());
-        let key = normalize_sql(&raw_sql);
+        let (key, table, op) = sql_key_labels(&raw_sql);
 
         // Store for finalization
         span.extensions_mut().insert(SqlSpanData {
             key: key.clone(),
+            table,
+            op,
             started_at: Instant::now(),
+            _token: begin_query(),
+            error: vis.error.clone(),
+            rows: vis.rows,
         });
 
         tracing::debug!(
@@ -90,7 +219,26 @@ where
         );
     }
 
-    // When span completes successfully
+    // Fields recorded after creation (e.g. an error set when the query fails)
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+
+        let mut exts = span.extensions_mut();
+        if let Some(data) = exts.get_mut::<SqlSpanData>() {
+            let mut vis = SqlVisitor::new();
+            values.record(&mut vis);
+            if vis.error.is_some() {
+                data.error = vis.error;
+            }
+            if let Some(rows) = vis.rows {
+                *data.rows.get_or_insert(0) += rows;
+            }
+        }
+    }
+
+    // When span completes (successfully or not)
     fn on_close(&self, id: Id, ctx: Context<'_, S>) {
         let span = match ctx.span(&id) {
             Some(s) => s,
@@ -105,6 +253,16 @@ where
 
             mark(QueryKind::Sql, &key);
             mark_latency(QueryKind::Sql, &key, ms);
+            if let Some(rows) = data.rows {
+                mark_rows(QueryKind::Sql, &key, rows);
+            }
+
+            prom::observe_sql_cmd(&data.table, &data.op, (ms as f64) / 1000.0);
+
+            if let Some(ref error) = data.error {
+                record_failure(&key, ms, error);
+                return;
+            }
 
             tracing::info!(
                 target = "MoniOF::sql",
@@ -112,23 +270,79 @@ where
                 latency_ms = %ms,
                 "SQL completed"
             );
+
+            let cfg = global();
+
+            if let Some(th) = cfg.slow_db_threshold_ms {
+                if ms >= th as u128 {
+                    tracing::warn!(
+                        target = "MoniOF::sql",
+                        key = %key,
+                        latency_ms = %ms,
+                        threshold_ms = th,
+                        "slow SQL query"
+                    );
+                    if alert::has_sink(&cfg) {
+                        let text = format!(
+                            "🐢 *Slow SQL query*\n• `key`: `{}`\n• `latency`: {} ms",
+                            key, ms
+                        );
+                        alert::dispatch(&cfg, AlertMessage { kinds: vec!["slow_sql"], text });
+                    }
+                }
+            }
+
+            if let Some(low) = cfg.low_db_threshold_ms {
+                if ms <= low as u128 {
+                    tracing::debug!(
+                        target = "MoniOF::sql",
+                        key = %key,
+                        latency_ms = %ms,
+                        threshold_ms = low,
+                        "very fast SQL query (check instrumentation/cache?)"
+                    );
+                }
+            }
         }
     }
 
-    // Handle SQL event-only mode (fallback)
-    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+    // Handle SQL event-only mode (fallback).
+    //
+    // Queries are counted once: if the event fires inside a SQL span, the
+    // span path counts it on close and the event only contributes its
+    // rows/error to that span. Events with no SQL span around them are
+    // counted here.
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
         let target = event.metadata().target();
-        if !target.starts_with("sqlx::query") {
+        if !is_sql_target(target) || !is_enabled() {
             return;
         }
 
         let mut vis = SqlVisitor::new();
         event.record(&mut vis);
 
+        if let Some(span) = ctx.event_span(event) {
+            for s in span.scope() {
+                let mut exts = s.extensions_mut();
+                if let Some(data) = exts.get_mut::<SqlSpanData>() {
+                    if vis.error.is_some() {
+                        data.error = vis.error;
+                    }
+                    if let Some(rows) = vis.rows {
+                        *data.rows.get_or_insert(0) += rows;
+                    }
+                    return;
+                }
+            }
+        }
+
         let raw_sql = vis.sql.unwrap_or_else(|| target.to_string());
-        let key = normalize_sql(&raw_sql);
+        let (key, _, _) = sql_key_labels(&raw_sql);
 
         mark(QueryKind::Sql, &key);
+        if let Some(rows) = vis.rows {
+            mark_rows(QueryKind::Sql, &key, rows);
+        }
 
         tracing::debug!(
             target = "MoniOF::sql",
@@ -136,5 +350,9 @@ where
             normalized = %key,
             "SQL event-only mode"
         );
+
+        if let Some(ref error) = vis.error {
+            record_failure(&key, 0, error);
+        }
     }
 }
@@ -0,0 +1,63 @@
+// src/instrumentation/sqlx_pool.rs
+#![cfg(feature = "sqlx")]
+
+// Pool-level SQLx metrics: per-query latency doesn't show the queueing a
+// saturated pool causes, so sample `Pool::size()` / `num_idle()` into
+// `moniof_sqlx_pool_connections` / `moniof_sqlx_pool_idle`.
+
+use crate::observability::prom;
+
+use sqlx::{Database, Pool};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// Running pool sampler; dropping it stops the sampler and removes the
+/// pool's gauges.
+#[must_use = "the sampler stops when the monitor is dropped"]
+pub struct PoolMonitor {
+    name: String,
+    task: JoinHandle<()>,
+}
+
+impl Drop for PoolMonitor {
+    fn drop(&mut self) {
+        self.task.abort();
+        prom::clear_sqlx_pool(&self.name);
+    }
+}
+
+/// Sample `pool` every `interval` under `pool="default"`. Call inside a
+/// tokio runtime and keep the returned monitor alive.
+///
+/// ```ignore
+/// let pool = PgPoolOptions::new().max_connections(20).connect(&url).await?;
+/// let _monitor = moniof::sqlx::monitor_pool(&pool, Duration::from_secs(5));
+/// ```
+pub fn monitor_pool<DB: Database>(pool: &Pool<DB>, interval: Duration) -> PoolMonitor {
+    monitor_pool_named(pool, "default", interval)
+}
+
+/// [`monitor_pool`] with a `pool` label, for apps with several pools.
+pub fn monitor_pool_named<DB: Database>(
+    pool: &Pool<DB>,
+    name: &str,
+    interval: Duration,
+) -> PoolMonitor {
+    prom::init_prometheus();
+
+    let pool = pool.clone();
+    let label = name.to_string();
+    let task = tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval.max(Duration::from_millis(100)));
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            if pool.is_closed() {
+                break;
+            }
+            prom::set_sqlx_pool(&label, pool.size(), pool.num_idle());
+        }
+    });
+
+    PoolMonitor { name: name.to_string(), task }
+}
@@ -0,0 +1,43 @@
+#![cfg(feature = "reqwest-tracking")]
+
+use reqwest::{Request, RequestBuilder, Response};
+
+use crate::core::stats::QueryKind;
+use crate::core::task_ctx::{track, QueryTimer};
+
+/// `<host>/<METHOD>`, counted under `other/` like any other query key.
+fn http_key(method: &str, host: &str) -> String {
+    format!("{}/{}", host, method)
+}
+
+fn request_key(req: &Request) -> String {
+    http_key(req.method().as_str(), req.url().host_str().unwrap_or("unknown"))
+}
+
+/// Count and time one outbound HTTP call under `other/<host>/<method>`.
+///
+/// For clients other than `reqwest`; latency is recorded when the returned
+/// timer drops.
+///
+/// ```ignore
+/// let _t = moniof::track_http("GET", "pricing.internal");
+/// let price = pricing_client.get_price(sku).await?;
+/// ```
+pub fn track_http(method: &str, host: &str) -> QueryTimer {
+    QueryTimer::start(QueryKind::Other, &http_key(method, host))
+}
+
+/// Send a `reqwest` request, recording it under `other/<host>/<method>`,
+/// so fan-out to other services counts toward `total` and N+1 detection.
+///
+/// ```ignore
+/// let res = moniof::send_tracked(client.get(url)).await?;
+/// ```
+///
+/// A request that fails to build is returned as an error without being counted.
+pub async fn send_tracked(builder: RequestBuilder) -> reqwest::Result<Response> {
+    let (client, req) = builder.build_split();
+    let req = req?;
+    let key = request_key(&req);
+    track(QueryKind::Other, &key, client.execute(req)).await
+}
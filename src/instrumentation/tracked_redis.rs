@@ -0,0 +1,63 @@
+#![cfg(feature = "redis")]
+
+use redis::aio::ConnectionManager;
+use redis::{AsyncCommands, FromRedisValue, RedisResult, ToRedisArgs};
+
+use crate::core::stats::QueryKind;
+use crate::core::task_ctx::track;
+
+/// Redis connection wrapper that counts and times commands per request.
+///
+/// Each call is recorded under `redis/<command>` (e.g. `redis/get`), so Redis
+/// shows up in the same per-request totals and N+1 detection as Mongo/SQL.
+/// For commands not wrapped here, use `inner()` together with `moniof::track`.
+#[derive(Clone)]
+pub struct TrackedRedis {
+    inner: ConnectionManager,
+}
+
+impl TrackedRedis {
+    pub fn new(inner: ConnectionManager) -> Self {
+        Self { inner }
+    }
+
+    /// Untracked access to the underlying connection manager.
+    pub fn inner(&mut self) -> &mut ConnectionManager {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> ConnectionManager {
+        self.inner
+    }
+
+    pub async fn get<K, RV>(&mut self, key: K) -> RedisResult<RV>
+    where
+        K: ToRedisArgs + Send + Sync,
+        RV: FromRedisValue,
+    {
+        track(QueryKind::Redis, "get", self.inner.get(key)).await
+    }
+
+    pub async fn mget<K, RV>(&mut self, keys: K) -> RedisResult<RV>
+    where
+        K: ToRedisArgs + Send + Sync,
+        RV: FromRedisValue,
+    {
+        track(QueryKind::Redis, "mget", self.inner.mget(keys)).await
+    }
+
+    pub async fn set<K, V, RV>(&mut self, key: K, value: V) -> RedisResult<RV>
+    where
+        K: ToRedisArgs + Send + Sync,
+        V: ToRedisArgs + Send + Sync,
+        RV: FromRedisValue,
+    {
+        track(QueryKind::Redis, "set", self.inner.set(key, value)).await
+    }
+}
+
+impl From<ConnectionManager> for TrackedRedis {
+    fn from(inner: ConnectionManager) -> Self {
+        Self::new(inner)
+    }
+}
@@ -4,14 +4,30 @@ pub mod core;
 pub mod instrumentation;
 pub mod observability;
 pub mod services;
+#[cfg(feature = "test-util")]
+pub mod test_util;
 
 // Keep public API roughly compatible:
-pub use config::{MoniOFGlobalConfig, initiate, global};
-pub use config::MoniOFConfig;
-pub use services::http::MoniOF;
+pub use config::{
+    MoniOFGlobalConfig, initiate, initiate_with_filter, global, is_enabled, is_strict, set_global,
+    update_global,
+};
+pub use config::{MoniOFConfig, NPlusOneRank, PathNormalizer};
+pub use services::http::{MoniOF, RequestSummary};
+pub use services::job::{instrument_job, instrument_job_with};
+
+// Manual instrumentation for custom data sources
+pub use core::{
+    QueryKind, QueryStatsHandle, QueryTimer, RelaxedLimits, mark, mark_latency, measure,
+    measure_async, set_dimension, spawn_tracked, track, with_relaxed_limits,
+};
 
 
 pub use observability::prom;
+pub use observability::slack::flush_alerts;
+pub use observability::alert::{AlertMessage, AlertSink};
+#[cfg(feature = "test-util")]
+pub use observability::alert::MemorySink;
 
 
 #[cfg(feature = "mongodb")]
@@ -19,4 +35,25 @@ pub use instrumentation::mongo_events::MOFMongoEvents;
 
 
 #[cfg(feature = "sqlx")]
-pub use instrumentation::sql_events::MOFSqlEvents;
+pub use instrumentation::sql_events::{MOFSqlEvents, moniof_layer};
+
+/// SQLx pool-level metrics (`moniof::sqlx::monitor_pool`).
+#[cfg(feature = "sqlx")]
+pub mod sqlx {
+    pub use crate::instrumentation::sqlx_pool::{PoolMonitor, monitor_pool, monitor_pool_named};
+}
+
+
+#[cfg(feature = "redis")]
+pub use instrumentation::tracked_redis::TrackedRedis;
+
+
+#[cfg(feature = "reqwest-tracking")]
+pub use instrumentation::tracked_http::{send_tracked, track_http};
+
+
+#[cfg(feature = "axum")]
+pub use services::axum::{MoniOFLayer, MoniOFService};
+
+#[cfg(feature = "tonic")]
+pub use services::grpc::{MoniOFGrpcLayer, MoniOFGrpcService};
@@ -0,0 +1,141 @@
+use futures_util::future::BoxFuture;
+
+use crate::config::MoniOFGlobalConfig;
+use crate::observability::{prom, slack};
+
+/// One alert, as handed to an [`AlertSink`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AlertMessage {
+    /// What fired: request-level kinds (`"max_total"`, `"same_key"`,
+    /// `"total_latency"`, `"low_latency"`, `"slowest_query"`,
+    /// `"dominant_key"`, `"n_plus_one"`) or a per-command kind
+    /// (`"slow_mongo"`, `"mongo_failed"`, `"slow_sql"`, `"sql_failed"`).
+    pub kinds: Vec<&'static str>,
+    /// Human-readable body (Slack markdown).
+    pub text: String,
+}
+
+/// Where alerts go. Defaults to Slack (`slack_webhook`); set
+/// `MoniOFGlobalConfig::alert_sink` to route them elsewhere.
+///
+/// `send` is called on the thread that raised the alert; the returned future
+/// is spawned on the current tokio runtime and counted by `flush_alerts`.
+pub trait AlertSink: Send + Sync {
+    fn send(&self, msg: AlertMessage) -> BoxFuture<'static, ()>;
+}
+
+/// Whether alerts would go anywhere (skip building the text if not).
+pub fn has_sink(cfg: &MoniOFGlobalConfig) -> bool {
+    cfg.alert_dry_run
+        || cfg.alert_sink.is_some() || cfg.slack_webhook.is_some() || !cfg.slack_routes.is_empty()
+}
+
+/// Slack webhook for `msg`: its first routed kind, else `slack_webhook`.
+fn slack_webhook_for<'a>(cfg: &'a MoniOFGlobalConfig, msg: &AlertMessage) -> Option<&'a String> {
+    msg.kinds
+        .iter()
+        .find_map(|kind| cfg.slack_routes.get(*kind))
+        .or(cfg.slack_webhook.as_ref())
+}
+
+/// Send `msg` to the configured sink in the background (or, with
+/// `alert_dry_run`, only log it).
+pub fn dispatch(cfg: &MoniOFGlobalConfig, msg: AlertMessage) {
+    if cfg.alert_dry_run {
+        tracing::info!(
+            target: "moniof::dryrun",
+            kinds = ?msg.kinds,
+            text = %msg.text,
+            "alert (dry run, not sent)"
+        );
+        prom::inc_alert_sent(true);
+        return;
+    }
+
+    let fut = match &cfg.alert_sink {
+        Some(sink) => sink.send(msg),
+        None => {
+            let Some(hook) = slack_webhook_for(cfg, &msg) else {
+                return;
+            };
+            Box::pin(slack::notify(Some(hook.clone()), msg.text))
+        }
+    };
+    prom::inc_alert_sent(false);
+    slack::spawn_delivery(fut);
+}
+
+/// In-memory sink that records every alert, for asserting alert behavior
+/// in tests.
+///
+/// ```ignore
+/// let sink = moniof::MemorySink::new();
+/// moniof::update_global(|g| g.alert_sink = Some(sink.handle()))?;
+/// // ... drive a request ...
+/// assert_eq!(sink.messages().len(), 1);
+/// ```
+#[cfg(feature = "test-util")]
+#[derive(Clone, Debug, Default)]
+pub struct MemorySink {
+    messages: std::sync::Arc<parking_lot::Mutex<Vec<AlertMessage>>>,
+}
+
+#[cfg(feature = "test-util")]
+impl MemorySink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// This sink as a value for `MoniOFGlobalConfig::alert_sink`.
+    pub fn handle(&self) -> std::sync::Arc<dyn AlertSink> {
+        std::sync::Arc::new(self.clone())
+    }
+
+    /// Alerts recorded so far, oldest first.
+    pub fn messages(&self) -> Vec<AlertMessage> {
+        self.messages.lock().clone()
+    }
+
+    /// Recorded alerts that include `kind`.
+    pub fn with_kind(&self, kind: &str) -> Vec<AlertMessage> {
+        self.messages
+            .lock()
+            .iter()
+            .filter(|m| m.kinds.contains(&kind))
+            .cloned()
+            .collect()
+    }
+
+    pub fn clear(&self) {
+        self.messages.lock().clear();
+    }
+}
+
+#[cfg(feature = "test-util")]
+impl AlertSink for MemorySink {
+    fn send(&self, msg: AlertMessage) -> BoxFuture<'static, ()> {
+        // recorded synchronously, so tests don't need to await the spawn
+        self.messages.lock().push(msg);
+        Box::pin(async {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routed_kind_uses_its_own_webhook() {
+        let cfg = MoniOFGlobalConfig::builder()
+            .slack_webhook("https://hooks.example/perf")
+            .slack_route("sql_failed", "https://hooks.example/oncall")
+            .build()
+            .unwrap();
+        let msg = |kinds| AlertMessage { kinds, text: String::new() };
+
+        let failed = msg(vec!["sql_failed"]);
+        assert_eq!(slack_webhook_for(&cfg, &failed).map(String::as_str), Some("https://hooks.example/oncall"));
+        let slow = msg(vec!["slow_sql"]);
+        assert_eq!(slack_webhook_for(&cfg, &slow).map(String::as_str), Some("https://hooks.example/perf"));
+    }
+}
@@ -0,0 +1,116 @@
+// src/observability/dogstatsd.rs
+#![cfg(feature = "datadog")]
+
+// Minimal DogStatsD client: fire-and-forget UDP datagrams to the Datadog
+// agent. Mirrors the Prometheus collectors (tags instead of labels); the
+// `prom::*` entry points call into here, so call sites stay unchanged.
+
+use parking_lot::RwLock;
+use std::fmt::Write;
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicI64, Ordering};
+
+struct Client {
+    addr: String,
+    socket: UdpSocket,
+}
+
+static CLIENT: RwLock<Option<Client>> = RwLock::new(None);
+static INFLIGHT: AtomicI64 = AtomicI64::new(0);
+
+/// (Re)point the client at `addr` (`host:port`), or disable it with `None`.
+/// Called whenever the global config is applied (config writers are
+/// serialized, so two calls don't race).
+pub(crate) fn configure(addr: Option<&str>) {
+    if CLIENT.read().as_ref().map(|c| c.addr.as_str()) == addr {
+        return;
+    }
+
+    // resolving `addr` can block on DNS; don't hold the lock `send` takes
+    let next = addr.and_then(|addr| match connect(addr) {
+        Ok(socket) => Some(Client { addr: addr.to_string(), socket }),
+        Err(e) => {
+            tracing::warn!(target = "moniof", addr, error = %e, "DogStatsD client disabled");
+            None
+        }
+    });
+    *CLIENT.write() = next;
+}
+
+/// Non-blocking UDP socket connected to `addr`, bound to the wildcard
+/// address of the same family (so an IPv6 agent works too).
+fn connect(addr: &str) -> io::Result<UdpSocket> {
+    let target = addr
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "address resolved to nothing"))?;
+    let local: SocketAddr = match target {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(local)?;
+    socket.connect(target)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket)
+}
+
+/// Tag values can't contain the protocol's separators.
+fn push_tag_value(buf: &mut String, v: &str) {
+    buf.extend(v.chars().map(|c| match c {
+        ',' | '|' | '#' | '\n' => '_',
+        c => c,
+    }));
+}
+
+fn send(name: &str, value: impl std::fmt::Display, kind: &str, tags: &[(&str, &str)]) {
+    let client = CLIENT.read();
+    let Some(client) = client.as_ref() else {
+        return;
+    };
+
+    let mut buf = String::with_capacity(96);
+    let _ = write!(buf, "moniof.{name}:{value}|{kind}");
+    for (i, (k, v)) in tags.iter().enumerate() {
+        buf.push_str(if i == 0 { "|#" } else { "," });
+        buf.push_str(k);
+        buf.push(':');
+        push_tag_value(&mut buf, v);
+    }
+
+    // dropped metrics are preferable to blocking a request
+    let _ = client.socket.send(buf.as_bytes());
+}
+
+pub(crate) fn count(name: &str, tags: &[(&str, &str)]) {
+    send(name, 1, "c", tags);
+}
+
+pub(crate) fn histogram(name: &str, value: f64, tags: &[(&str, &str)]) {
+    send(name, value, "h", tags);
+}
+
+pub(crate) fn gauge(name: &str, value: i64, tags: &[(&str, &str)]) {
+    send(name, value, "g", tags);
+}
+
+pub(crate) fn inflight_delta(delta: i64) {
+    let now = INFLIGHT.fetch_add(delta, Ordering::Relaxed) + delta;
+    send("http.inflight_requests", now, "g", &[]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connect_binds_the_target_address_family() {
+        let v4 = connect("127.0.0.1:8125").unwrap();
+        assert!(v4.local_addr().unwrap().is_ipv4());
+        // hosts without IPv6 loopback (some CI containers) can't check this half
+        if UdpSocket::bind("[::1]:0").is_ok() {
+            let v6 = connect("[::1]:8125").unwrap();
+            assert!(v6.local_addr().unwrap().is_ipv6());
+        }
+    }
+}
@@ -0,0 +1,62 @@
+// Alert escalation: log every time, page Slack only once the same route has
+// tripped the same alert kind `escalate_after` times within a window.
+
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use std::time::{Duration, Instant};
+
+struct Window {
+    started_at: Instant,
+    count: usize,
+}
+
+/// (route, alert kind) → occurrences in the current window.
+static WINDOWS: Lazy<DashMap<(String, &'static str), Window>> = Lazy::new(DashMap::new);
+
+/// Beyond this many (route, kind) pairs, new pairs page immediately
+/// (fail open) instead of being tracked.
+const MAX_TRACKED_WINDOWS: usize = 10_000;
+
+/// Count one occurrence of `kind` on `route`; `true` when it should page.
+///
+/// The window starts at the first occurrence and resets when it expires or
+/// after firing. `after <= 1` pages every time (no escalation).
+pub(crate) fn should_page(route: &str, kind: &'static str, after: usize, window: Duration) -> bool {
+    if after <= 1 {
+        return true;
+    }
+
+    let key = (route.to_string(), kind);
+    if !WINDOWS.contains_key(&key) && WINDOWS.len() >= MAX_TRACKED_WINDOWS {
+        return true;
+    }
+
+    let now = Instant::now();
+    let mut w = WINDOWS.entry(key).or_insert(Window { started_at: now, count: 0 });
+    if now.duration_since(w.started_at) > window {
+        w.started_at = now;
+        w.count = 0;
+    }
+    w.count += 1;
+
+    if w.count >= after {
+        w.started_at = now;
+        w.count = 0;
+        true
+    } else {
+        false
+    }
+}
+
+/// Current (route, alert kind, count) windows, for debugging escalation.
+pub fn escalation_counts() -> Vec<(String, &'static str, usize)> {
+    WINDOWS
+        .iter()
+        .map(|e| (e.key().0.clone(), e.key().1, e.value().count))
+        .collect()
+}
+
+/// Forget all escalation windows.
+pub fn reset_escalation() {
+    WINDOWS.clear();
+}
@@ -1,3 +1,12 @@
 pub mod prom;
 pub mod slack;
+pub mod alert;
 pub mod of;
+pub mod escalation;
+pub(crate) mod summary;
+
+#[cfg(feature = "otel")]
+pub(crate) mod otel;
+
+#[cfg(feature = "datadog")]
+pub(crate) mod dogstatsd;
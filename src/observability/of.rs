@@ -1,6 +1,9 @@
-use crate::config::MoniOFConfig;
+use crate::config::{MoniOFConfig, NPlusOneRank};
 use crate::core::stats::QueryStats;
 
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+
 #[derive(Debug, Clone)]
 pub struct OfSuspect {
     pub key: String,
@@ -8,6 +11,15 @@ pub struct OfSuspect {
     pub total_latency_ms: u128,
 }
 
+impl OfSuspect {
+    /// `count × total_latency_ms`, the `NPlusOneRank::Score` ranking.
+    pub fn score(&self) -> u128 {
+        self.count as u128 * self.total_latency_ms
+    }
+}
+
+/// Keys that look like N+1 loops, best first (at most 3), gated and ranked
+/// per `cfg.n_plus_one_rank_by`.
 pub fn find_suspects(stats: &QueryStats, cfg: &MoniOFConfig) -> Vec<OfSuspect> {
     if !cfg.of_mode {
         return Vec::new();
@@ -16,16 +28,20 @@ pub fn find_suspects(stats: &QueryStats, cfg: &MoniOFConfig) -> Vec<OfSuspect> {
     let mut suspects = Vec::new();
 
     for (k, count) in &stats.per_key {
-        if *count < cfg.n_plus_one_min_count {
-            continue;
-        }
-
         let total_ms = stats.per_key_latency_ms.get(k).copied().unwrap_or(0);
-
-        if let Some(min_ms) = cfg.n_plus_one_min_total_ms {
-            if total_ms < min_ms {
-                continue;
+        let qualifies = match cfg.n_plus_one_rank_by {
+            NPlusOneRank::Count | NPlusOneRank::Score => {
+                *count >= stats.n_plus_one_min_count_for(k, cfg.n_plus_one_min_count)
+                    && cfg.n_plus_one_min_total_ms.map_or(true, |min| total_ms >= min)
+            }
+            // any repeat; keys relaxed by `with_relaxed_limits` keep their limit
+            NPlusOneRank::TotalLatency => {
+                *count >= stats.n_plus_one_min_count_for(k, 2)
+                    && total_ms >= cfg.n_plus_one_min_total_ms.unwrap_or(0)
             }
+        };
+        if !qualifies {
+            continue;
         }
 
         suspects.push(OfSuspect {
@@ -35,9 +51,17 @@ pub fn find_suspects(stats: &QueryStats, cfg: &MoniOFConfig) -> Vec<OfSuspect> {
         });
     }
 
-    suspects.sort_by(|a, b| {
-        b.count.cmp(&a.count).then_with(|| b.total_latency_ms.cmp(&a.total_latency_ms))
-    });
+    match cfg.n_plus_one_rank_by {
+        NPlusOneRank::Count => suspects.sort_by(|a, b| {
+            b.count.cmp(&a.count).then_with(|| b.total_latency_ms.cmp(&a.total_latency_ms))
+        }),
+        NPlusOneRank::TotalLatency => suspects.sort_by(|a, b| {
+            b.total_latency_ms.cmp(&a.total_latency_ms).then_with(|| b.count.cmp(&a.count))
+        }),
+        NPlusOneRank::Score => suspects.sort_by(|a, b| {
+            b.score().cmp(&a.score()).then_with(|| b.count.cmp(&a.count))
+        }),
+    }
 
     if suspects.len() > 3 {
         suspects.truncate(3);
@@ -45,3 +69,132 @@ pub fn find_suspects(stats: &QueryStats, cfg: &MoniOFConfig) -> Vec<OfSuspect> {
 
     suspects
 }
+
+// -------------------------------------------------------
+// Process-wide leaderboard
+// -------------------------------------------------------
+/// How often a key was flagged as an N+1 suspect, across requests.
+#[derive(Debug, Clone, Default)]
+pub struct SuspectAgg {
+    /// Requests in which the key was a suspect.
+    pub occurrences: u64,
+    /// Queries issued for the key in those requests.
+    pub total_queries: u64,
+    /// Latency spent on the key in those requests (ms).
+    pub total_latency_ms: u128,
+    /// Route of the most recent flagged request, if it had one.
+    pub last_route: Option<String>,
+}
+
+static OFFENDERS: Lazy<DashMap<String, SuspectAgg>> = Lazy::new(DashMap::new);
+
+/// Keys beyond this are not tracked until `reset_offenders`.
+const MAX_TRACKED_OFFENDERS: usize = 1_000;
+
+/// Fold one request's suspects into the leaderboard.
+pub fn record_offenders(suspects: &[OfSuspect], route: Option<&str>) {
+    for s in suspects {
+        if !OFFENDERS.contains_key(&s.key) && OFFENDERS.len() >= MAX_TRACKED_OFFENDERS {
+            continue;
+        }
+        let mut agg = OFFENDERS.entry(s.key.clone()).or_default();
+        agg.occurrences += 1;
+        agg.total_queries += s.count as u64;
+        agg.total_latency_ms += s.total_latency_ms;
+        if let Some(route) = route {
+            agg.last_route = Some(route.to_string());
+        }
+    }
+}
+
+/// The `n` keys flagged most often (ties: most latency first).
+pub fn top_offenders(n: usize) -> Vec<(String, SuspectAgg)> {
+    let mut all: Vec<_> = OFFENDERS
+        .iter()
+        .map(|e| (e.key().clone(), e.value().clone()))
+        .collect();
+    all.sort_by(|(_, a), (_, b)| {
+        b.occurrences
+            .cmp(&a.occurrences)
+            .then_with(|| b.total_latency_ms.cmp(&a.total_latency_ms))
+    });
+    all.truncate(n);
+    all
+}
+
+/// Clear the leaderboard (e.g. after a deploy or from an admin endpoint).
+pub fn reset_offenders() {
+    OFFENDERS.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `cheap`: 6 × ~0.2 ms (0 ms each), `slow`: 6 × 80 ms,
+    /// `chatty`: 20 × 1 ms, `heavy`: 3 × 100 ms.
+    fn stats() -> QueryStats {
+        let mut stats = QueryStats::new();
+        for (key, repeat, ms) in [
+            ("mongo/cheap/find", 6, 0),
+            ("mongo/slow/find", 6, 80),
+            ("mongo/chatty/find", 20, 1),
+            ("mongo/heavy/find", 3, 100),
+        ] {
+            for _ in 0..repeat {
+                stats.record(key);
+                stats.record_latency(key, ms);
+            }
+        }
+        stats
+    }
+
+    fn keys(rank: NPlusOneRank, min_total_ms: Option<u128>) -> Vec<String> {
+        let cfg = MoniOFConfig::builder()
+            .n_plus_one_min_count(5)
+            .n_plus_one_min_total_ms(min_total_ms)
+            .n_plus_one_rank_by(rank)
+            .build();
+        find_suspects(&stats(), &cfg).into_iter().map(|s| s.key).collect()
+    }
+
+    #[test]
+    fn count_gates_on_repeats_and_ranks_most_repeated_first() {
+        // count ties (cheap vs slow) break on latency
+        assert_eq!(
+            keys(NPlusOneRank::Count, None),
+            ["mongo/chatty/find", "mongo/slow/find", "mongo/cheap/find"]
+        );
+        // the latency gate drops the cheap loop
+        assert_eq!(
+            keys(NPlusOneRank::Count, Some(5)),
+            ["mongo/chatty/find", "mongo/slow/find"]
+        );
+    }
+
+    #[test]
+    fn total_latency_gates_on_time_and_ranks_most_expensive_first() {
+        // 3 repeats is below min count, but 300 ms qualifies
+        assert_eq!(
+            keys(NPlusOneRank::TotalLatency, Some(100)),
+            ["mongo/slow/find", "mongo/heavy/find"]
+        );
+    }
+
+    #[test]
+    fn total_latency_without_latency_gate_flags_any_repeat() {
+        assert_eq!(
+            keys(NPlusOneRank::TotalLatency, None),
+            ["mongo/slow/find", "mongo/heavy/find", "mongo/chatty/find"]
+        );
+    }
+
+    #[test]
+    fn score_applies_both_gates_and_ranks_by_count_times_latency() {
+        // slow: 6 × 480 = 2880, chatty: 20 × 20 = 400; cheap fails the latency gate
+        assert_eq!(
+            keys(NPlusOneRank::Score, Some(5)),
+            ["mongo/slow/find", "mongo/chatty/find"]
+        );
+    }
+}
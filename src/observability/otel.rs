@@ -0,0 +1,106 @@
+// src/observability/otel.rs
+#![cfg(feature = "otel")]
+
+//! OpenTelemetry export, alongside (or instead of) the Prometheus registry.
+//!
+//! Uses the global tracer / meter providers, so install your SDK pipeline
+//! (`opentelemetry::global::set_tracer_provider` / `set_meter_provider`)
+//! before serving requests.
+
+use once_cell::sync::OnceCell;
+use opentelemetry::{
+    global,
+    metrics::Histogram,
+    trace::{Span, SpanKind, Tracer},
+    KeyValue,
+};
+use std::time::{Duration, SystemTime};
+
+struct Instruments {
+    http_duration: Histogram<f64>,
+    db_total: Histogram<f64>,
+    queries: Histogram<u64>,
+}
+
+static INSTRUMENTS: OnceCell<Instruments> = OnceCell::new();
+
+fn instruments() -> &'static Instruments {
+    INSTRUMENTS.get_or_init(|| {
+        let meter = global::meter("moniof");
+        Instruments {
+            http_duration: meter
+                .f64_histogram("moniof.http.request.duration")
+                .with_unit("s")
+                .with_description("HTTP request duration")
+                .build(),
+            db_total: meter
+                .f64_histogram("moniof.db.total_latency")
+                .with_unit("s")
+                .with_description("Cumulative DB latency per request, by kind")
+                .build(),
+            queries: meter
+                .u64_histogram("moniof.db.queries")
+                .with_description("DB queries per request")
+                .build(),
+        }
+    })
+}
+
+/// Per-request figures exported as span attributes and metrics.
+pub(crate) struct OtelRequest<'a> {
+    pub request_id: &'a str,
+    pub method: &'a str,
+    pub route: Option<&'a str>,
+    pub status: u16,
+    pub duration_s: f64,
+    pub total: usize,
+    pub db_total_ms: u128,
+    pub db_by_kind: &'a [(&'a str, f64)],
+    pub slowest_key: Option<&'a str>,
+    pub n_plus_one_suspects: usize,
+}
+
+/// One `moniof.request` span (backdated to the request start, child of the
+/// current OTel context) plus the request metrics.
+pub(crate) fn record_request(r: &OtelRequest<'_>) {
+    let route = r.route.unwrap_or("unmatched").to_string();
+    let http_attrs = [
+        KeyValue::new("http.request.method", r.method.to_string()),
+        KeyValue::new("http.route", route.clone()),
+        KeyValue::new("http.response.status_code", r.status as i64),
+    ];
+
+    let mut attrs = http_attrs.to_vec();
+    attrs.extend([
+        KeyValue::new("moniof.request_id", r.request_id.to_string()),
+        KeyValue::new("moniof.total", r.total as i64),
+        KeyValue::new("moniof.db_total_ms", r.db_total_ms as i64),
+        KeyValue::new("moniof.n_plus_one_suspects", r.n_plus_one_suspects as i64),
+    ]);
+    if let Some(k) = r.slowest_key {
+        attrs.push(KeyValue::new("moniof.slowest_key", k.to_string()));
+    }
+
+    let end = SystemTime::now();
+    let start = end
+        .checked_sub(Duration::from_secs_f64(r.duration_s))
+        .unwrap_or(end);
+    let tracer = global::tracer("moniof");
+    let mut span = tracer
+        .span_builder("moniof.request")
+        .with_kind(SpanKind::Server)
+        .with_start_time(start)
+        .with_attributes(attrs)
+        .start(&tracer);
+    span.end_with_timestamp(end);
+
+    let i = instruments();
+    i.http_duration.record(r.duration_s, &http_attrs);
+    i.queries.record(r.total as u64, &[KeyValue::new("http.route", route.clone())]);
+    for (kind, secs) in r.db_by_kind {
+        i.db_total.record(
+            *secs,
+            &[KeyValue::new("db.kind", kind.to_string()), KeyValue::new("http.route", route.clone())],
+        );
+    }
+}
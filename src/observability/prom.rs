@@ -1,7 +1,17 @@
 use actix_web::{HttpResponse};
-use once_cell::sync::OnceCell;
+use dashmap::DashMap;
+use once_cell::sync::{Lazy, OnceCell};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+#[cfg(feature = "datadog")]
+use crate::observability::dogstatsd;
+use crate::observability::summary::SummaryVec;
+use crate::config::{is_strict, MoniOFGlobalConfig};
 use prometheus::{
-    Encoder, HistogramOpts, HistogramVec, IntGauge, IntCounterVec, Opts, Registry, TextEncoder,
+    core::Collector,
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntGauge, IntGaugeVec, IntCounterVec, Opts,
+    Registry,
+    TextEncoder,
 };
 
 static REGISTRY: OnceCell<Registry> = OnceCell::new();
@@ -9,80 +19,480 @@ static REGISTRY: OnceCell<Registry> = OnceCell::new();
 static HTTP_REQ_COUNTER: OnceCell<IntCounterVec> = OnceCell::new();
 static HTTP_INFLIGHT: OnceCell<IntGauge> = OnceCell::new();
 static HTTP_REQ_HISTO: OnceCell<HistogramVec> = OnceCell::new();
+static HTTP_APP_HISTO: OnceCell<HistogramVec> = OnceCell::new();
 
 static DB_TOTAL_HISTO: OnceCell<HistogramVec> = OnceCell::new();
+static DB_BUSY_HISTO: OnceCell<HistogramVec> = OnceCell::new();
+static DB_DISTINCT_KEYS_HISTO: OnceCell<HistogramVec> = OnceCell::new();
 static MONGO_CMD_HISTO: OnceCell<HistogramVec> = OnceCell::new();
+static MONGO_POOL_WAIT_HISTO: OnceCell<HistogramVec> = OnceCell::new();
+static MONGO_CMD_SUMMARY: OnceCell<SummaryVec> = OnceCell::new();
+static MONGO_RETRIES: OnceCell<IntCounterVec> = OnceCell::new();
+static MONGO_COLD_HISTO: OnceCell<HistogramVec> = OnceCell::new();
+static MONGO_REPLY_BYTES_HISTO: OnceCell<HistogramVec> = OnceCell::new();
+static SQL_CMD_HISTO: OnceCell<HistogramVec> = OnceCell::new();
+static SQL_ERRORS: OnceCell<IntCounter> = OnceCell::new();
+static SQLX_POOL_CONNS: OnceCell<IntGaugeVec> = OnceCell::new();
+static SQLX_POOL_IDLE: OnceCell<IntGaugeVec> = OnceCell::new();
+static ALERTS_SENT: OnceCell<IntCounterVec> = OnceCell::new();
+static REQUEST_QUERIES_HISTO: OnceCell<HistogramVec> = OnceCell::new();
+static JOB_QUERIES_HISTO: OnceCell<HistogramVec> = OnceCell::new();
+
+/// Dimension values already emitted as labels (see `observe_request_queries`).
+static DIMENSION_VALUES: Lazy<DashMap<String, ()>> = Lazy::new(DashMap::new);
 
 fn default_buckets_seconds() -> Vec<f64> {
     // Prometheus-default-ish buckets for latency (seconds)
     vec![0.005,0.01,0.025,0.05,0.1,0.25,0.5,1.0,2.5,5.0,10.0]
 }
 
+/// `environment` and `mongo_command_quantiles` as the registry was built
+/// with; both are fixed once it exists.
+static BUILT_WITH: OnceCell<(Option<String>, Vec<f64>)> = OnceCell::new();
+
+fn registry_environment(cfg: &MoniOFGlobalConfig) -> Option<String> {
+    cfg.environment.clone().filter(|e| !e.trim().is_empty())
+}
+
+/// Called when a global config is applied: changing a registry-time setting
+/// after `init_prometheus` has no effect, so say so instead of ignoring it.
+pub(crate) fn warn_if_registry_settings_changed(cfg: &MoniOFGlobalConfig) {
+    let Some((env, quantiles)) = BUILT_WITH.get() else {
+        return;
+    };
+    if registry_environment(cfg) != *env {
+        tracing::warn!(
+            target = "moniof",
+            registry = ?env,
+            requested = ?cfg.environment,
+            "environment changed after init_prometheus; the metrics keep the old label"
+        );
+    }
+    if cfg.mongo_command_quantiles != *quantiles {
+        tracing::warn!(
+            target = "moniof",
+            registry = ?quantiles,
+            requested = ?cfg.mongo_command_quantiles,
+            "mongo_command_quantiles changed after init_prometheus; not applied"
+        );
+    }
+}
+
+/// Registry for all moniof collectors; `environment` (if set) becomes a
+/// constant label on every series it exposes.
+fn new_registry() -> Registry {
+    let cfg = crate::config::global();
+    BUILT_WITH.get_or_init(|| (registry_environment(&cfg), cfg.mongo_command_quantiles.clone()));
+    let Some(env) = registry_environment(&cfg) else {
+        return Registry::new();
+    };
+    let labels = HashMap::from([("environment".to_string(), env)]);
+    Registry::new_custom(None, Some(labels)).unwrap_or_default()
+}
+
+/// Register `collector` and store it in `cell`. A collector that fails to
+/// build or register (bad options, a name clash in the registry) is logged
+/// and left out: `cell` stays unset, so its observe functions are no-ops
+/// instead of taking the service down at boot. No-op once `cell` is set.
+fn install<C>(registry: &Registry, cell: &OnceCell<C>, name: &str, collector: prometheus::Result<C>)
+where
+    C: Collector + Clone + 'static,
+{
+    if cell.get().is_some() {
+        return;
+    }
+    let registered = collector.and_then(|c| registry.register(Box::new(c.clone())).map(|_| c));
+    match registered {
+        Ok(c) => {
+            cell.set(c).ok();
+        }
+        Err(e) => tracing::error!(
+            target = "moniof",
+            metric = name,
+            error = %e,
+            "failed to initialize Prometheus collector; metric disabled"
+        ),
+    }
+}
+
 pub fn init_prometheus() {
-    let registry = REGISTRY.get_or_init(Registry::new);
+    let registry = REGISTRY.get_or_init(new_registry);
 
-    let http_counter = IntCounterVec::new(
+    install(registry, &HTTP_REQ_COUNTER, "moniof_http_requests_total", IntCounterVec::new(
         Opts::new("moniof_http_requests_total", "HTTP requests total"),
         &["method", "status"],
-    ).unwrap();
+    ));
 
-    let http_inflight = IntGauge::new("moniof_http_inflight_requests", "Inflight HTTP requests").unwrap();
+    install(registry, &HTTP_INFLIGHT, "moniof_http_inflight_requests",
+        IntGauge::new("moniof_http_inflight_requests", "Inflight HTTP requests"));
 
-    let http_histo = HistogramVec::new(
+    install(registry, &HTTP_REQ_HISTO, "moniof_http_request_duration_seconds", HistogramVec::new(
         HistogramOpts::new("moniof_http_request_duration_seconds", "HTTP request duration (s)")
             .buckets(default_buckets_seconds()),
         &["method"],
-    ).unwrap();
+    ));
 
-    let db_total = HistogramVec::new(
+    install(registry, &HTTP_APP_HISTO, "moniof_http_app_time_seconds", HistogramVec::new(
+        HistogramOpts::new("moniof_http_app_time_seconds", "Request time spent outside the DB (s)")
+            .buckets(default_buckets_seconds()),
+        &["method"],
+    ));
+
+    install(registry, &DB_TOTAL_HISTO, "moniof_db_total_latency_seconds", HistogramVec::new(
         HistogramOpts::new("moniof_db_total_latency_seconds", "Cumulative DB latency per request (s)")
             .buckets(default_buckets_seconds()),
-        &["kind"], // e.g., "mongo" (aggregate), room for "sql" later if you wish to split
-    ).unwrap();
+        &["kind"], // "mongo" | "sql" | "redis" | "other"; only kinds the request used
+    ));
+
+    install(registry, &DB_BUSY_HISTO, "moniof_db_busy_seconds", HistogramVec::new(
+        HistogramOpts::new(
+            "moniof_db_busy_seconds",
+            "Wall time per request with at least one query in flight (s)",
+        )
+        .buckets(default_buckets_seconds()),
+        &["method"],
+    ));
+
+    install(registry, &DB_DISTINCT_KEYS_HISTO, "moniof_db_distinct_keys_per_request", HistogramVec::new(
+        HistogramOpts::new(
+            "moniof_db_distinct_keys_per_request",
+            "Distinct query keys per request (see moniof_request_queries for the total)",
+        )
+        .buckets(vec![0.0, 1.0, 2.0, 5.0, 10.0, 20.0, 50.0, 100.0, 200.0]),
+        &["method"],
+    ));
 
-    let mongo_cmd = HistogramVec::new(
+    install(registry, &MONGO_CMD_HISTO, "moniof_mongo_command_duration_seconds", HistogramVec::new(
         HistogramOpts::new("moniof_mongo_command_duration_seconds", "Single Mongo command latency (s)")
             .buckets(default_buckets_seconds()),
         &["collection","op"],
-    ).unwrap();
+    ));
+
+    // opt-in: summaries cost more than the histogram
+    // as recorded when the registry was created
+    let quantiles = BUILT_WITH.get().map(|(_, q)| q.clone()).unwrap_or_default();
+    if !quantiles.is_empty() {
+        install(registry, &MONGO_CMD_SUMMARY, "moniof_mongo_command_quantiles", SummaryVec::new(
+            "moniof_mongo_command_quantiles",
+            "Single Mongo command latency quantiles over recent commands (s)",
+            &["collection","op"],
+            quantiles,
+        ));
+    }
+
+    install(registry, &MONGO_POOL_WAIT_HISTO, "moniof_mongo_pool_wait_seconds", HistogramVec::new(
+        HistogramOpts::new("moniof_mongo_pool_wait_seconds", "Mongo connection checkout wait (s)")
+            .buckets(default_buckets_seconds()),
+        &["outcome"], // "checked_out" | "failed"
+    ));
+
+    install(registry, &MONGO_COLD_HISTO, "moniof_mongo_cold_command_seconds", HistogramVec::new(
+        HistogramOpts::new(
+            "moniof_mongo_cold_command_seconds",
+            "First Mongo command per (collection, op) in this process, incl. warmup (s)",
+        )
+        .buckets(default_buckets_seconds()),
+        &["collection","op"],
+    ));
+
+    install(registry, &MONGO_REPLY_BYTES_HISTO, "moniof_mongo_reply_bytes",
+        prometheus::exponential_buckets(256.0, 4.0, 9).and_then(|buckets| { // 256B .. 16MB
+            HistogramVec::new(
+                HistogramOpts::new("moniof_mongo_reply_bytes", "Estimated Mongo reply size (bytes)")
+                    .buckets(buckets),
+                &["collection","op"],
+            )
+        }));
 
-    registry.register(Box::new(http_counter.clone())).ok();
-    registry.register(Box::new(http_inflight.clone())).ok();
-    registry.register(Box::new(http_histo.clone())).ok();
-    registry.register(Box::new(db_total.clone())).ok();
-    registry.register(Box::new(mongo_cmd.clone())).ok();
+    install(registry, &MONGO_RETRIES, "moniof_mongo_retries_total", IntCounterVec::new(
+        Opts::new("moniof_mongo_retries_total", "Retried Mongo write attempts (counted once in stats)"),
+        &["collection","op"],
+    ));
 
-    HTTP_REQ_COUNTER.set(http_counter).ok();
-    HTTP_INFLIGHT.set(http_inflight).ok();
-    HTTP_REQ_HISTO.set(http_histo).ok();
-    DB_TOTAL_HISTO.set(db_total).ok();
-    MONGO_CMD_HISTO.set(mongo_cmd).ok();
+    install(registry, &SQL_CMD_HISTO, "moniof_sql_command_duration_seconds", HistogramVec::new(
+        HistogramOpts::new("moniof_sql_command_duration_seconds", "Single SQL query latency (s)")
+            .buckets(default_buckets_seconds()),
+        &["table","op"],
+    ));
+
+    install(registry, &SQL_ERRORS, "moniof_sql_errors_total",
+        IntCounter::new("moniof_sql_errors_total", "Failed SQL queries"));
+
+    install(registry, &SQLX_POOL_CONNS, "moniof_sqlx_pool_connections", IntGaugeVec::new(
+        Opts::new("moniof_sqlx_pool_connections", "Open SQLx pool connections (idle + in use)"),
+        &["pool"], // `monitor_pool_named` name, "default" otherwise
+    ));
+
+    install(registry, &SQLX_POOL_IDLE, "moniof_sqlx_pool_idle", IntGaugeVec::new(
+        Opts::new("moniof_sqlx_pool_idle", "Idle SQLx pool connections"),
+        &["pool"],
+    ));
+
+    install(registry, &ALERTS_SENT, "moniof_alerts_sent_total", IntCounterVec::new(
+        Opts::new("moniof_alerts_sent_total", "Alerts dispatched (or logged, in dry-run mode)"),
+        &["dry_run"], // "true" | "false"
+    ));
+
+    install(registry, &REQUEST_QUERIES_HISTO, "moniof_request_queries", HistogramVec::new(
+        HistogramOpts::new("moniof_request_queries", "Queries per request, by request dimension (if configured)")
+            .buckets(vec![1.0, 2.0, 5.0, 10.0, 20.0, 50.0, 100.0, 200.0, 500.0]),
+        &["dimension"], // value of `query_count_dimension`, "unset" or "other"
+    ));
+
+    install(registry, &JOB_QUERIES_HISTO, "moniof_job_queries", HistogramVec::new(
+        HistogramOpts::new("moniof_job_queries", "Queries per background job run")
+            .buckets(vec![1.0, 2.0, 5.0, 10.0, 20.0, 50.0, 100.0, 200.0, 500.0, 1000.0, 5000.0]),
+        &["job"], // `instrument_job` name
+    ));
+}
+
+/// Reported once per metric (strict mode).
+static REPORTED_UNSET: Lazy<DashMap<&'static str, ()>> = Lazy::new(DashMap::new);
+
+/// `cell`'s collector. In `strict` mode, a missing one (`init_prometheus`
+/// never ran, or the collector failed to initialize) is logged as an error,
+/// once per metric; otherwise observations are silently dropped.
+fn metric<'a, C>(cell: &'a OnceCell<C>, name: &'static str) -> Option<&'a C> {
+    let c = cell.get();
+    if c.is_none() && is_strict() && REPORTED_UNSET.insert(name, ()).is_none() {
+        tracing::error!(
+            target = "moniof",
+            metric = name,
+            "strict: metric observed before init_prometheus (or its collector failed); \
+             observation dropped"
+        );
+    }
+    c
 }
 
-// Called by middleware
+// Called by middleware.
+// Each entry point also feeds the DogStatsD backend when `datadog` is enabled.
 pub fn inc_inflight() {
-    if let Some(g) = HTTP_INFLIGHT.get() { g.inc(); }
+    if let Some(g) = metric(&HTTP_INFLIGHT, "moniof_http_inflight_requests") { g.inc(); }
+    #[cfg(feature = "datadog")]
+    dogstatsd::inflight_delta(1);
 }
 pub fn dec_inflight() {
-    if let Some(g) = HTTP_INFLIGHT.get() { g.dec(); }
+    if let Some(g) = metric(&HTTP_INFLIGHT, "moniof_http_inflight_requests") { g.dec(); }
+    #[cfg(feature = "datadog")]
+    dogstatsd::inflight_delta(-1);
+}
+/// Holds one slot of the inflight gauge; decrements on drop, so the gauge
+/// can't leak when the request future errors, panics or is dropped.
+pub struct InflightGuard(());
+
+impl InflightGuard {
+    pub fn new() -> Self {
+        inc_inflight();
+        Self(())
+    }
+}
+
+impl Default for InflightGuard {
+    fn default() -> Self {
+        Self::new()
+    }
 }
-pub fn observe_request(method: &str, status: u16, dur_seconds: f64, db_total_seconds: f64) {
-    if let Some(c) = HTTP_REQ_COUNTER.get() {
+
+impl Drop for InflightGuard {
+    fn drop(&mut self) {
+        dec_inflight();
+    }
+}
+
+/// `db_by_kind`: cumulative DB seconds per kind (`"mongo"`, `"sql"`, ...),
+/// one `moniof_db_total_latency_seconds` sample each.
+pub fn observe_request(method: &str, status: u16, dur_seconds: f64, db_by_kind: &[(&str, f64)]) {
+    observe_http(method, status, dur_seconds);
+    if let Some(h) = metric(&DB_TOTAL_HISTO, "moniof_db_total_latency_seconds") {
+        for (kind, secs) in db_by_kind {
+            h.with_label_values(&[kind]).observe(*secs);
+        }
+    }
+    #[cfg(feature = "datadog")]
+    for (kind, secs) in db_by_kind {
+        dogstatsd::histogram("db.total_latency_seconds", *secs, &[("kind", kind)]);
+    }
+}
+
+/// HTTP counter + duration only (no DB stats), e.g. for unsampled requests.
+pub fn observe_http(method: &str, status: u16, dur_seconds: f64) {
+    if let Some(c) = metric(&HTTP_REQ_COUNTER, "moniof_http_requests_total") {
         c.with_label_values(&[method, &status.to_string()]).inc();
     }
-    if let Some(h) = HTTP_REQ_HISTO.get() {
+    if let Some(h) = metric(&HTTP_REQ_HISTO, "moniof_http_request_duration_seconds") {
         h.with_label_values(&[method]).observe(dur_seconds);
     }
-    if let Some(h) = DB_TOTAL_HISTO.get() {
-        h.with_label_values(&["all"]).observe(db_total_seconds);
+    #[cfg(feature = "datadog")]
+    {
+        let status = status.to_string();
+        dogstatsd::count("http.requests", &[("method", method), ("status", &status)]);
+        dogstatsd::histogram("http.request_duration_seconds", dur_seconds, &[("method", method)]);
+    }
+}
+
+/// Wall time minus cumulative DB time (clamped at 0) for one request.
+pub fn observe_app_time(method: &str, app_seconds: f64) {
+    if let Some(h) = metric(&HTTP_APP_HISTO, "moniof_http_app_time_seconds") {
+        h.with_label_values(&[method]).observe(app_seconds);
+    }
+    #[cfg(feature = "datadog")]
+    dogstatsd::histogram("http.app_time_seconds", app_seconds, &[("method", method)]);
+}
+
+/// DB busy time (union of in-flight query intervals) for one request.
+pub fn observe_db_busy(method: &str, busy_seconds: f64) {
+    if let Some(h) = metric(&DB_BUSY_HISTO, "moniof_db_busy_seconds") {
+        h.with_label_values(&[method]).observe(busy_seconds);
+    }
+    #[cfg(feature = "datadog")]
+    dogstatsd::histogram("db.busy_seconds", busy_seconds, &[("method", method)]);
+}
+
+/// Distinct query keys (`stats.per_key.len()`) for one request.
+pub fn observe_db_distinct_keys(method: &str, distinct: usize) {
+    if let Some(h) = metric(&DB_DISTINCT_KEYS_HISTO, "moniof_db_distinct_keys_per_request") {
+        h.with_label_values(&[method]).observe(distinct as f64);
     }
+    #[cfg(feature = "datadog")]
+    dogstatsd::histogram("db.distinct_keys_per_request", distinct as f64, &[("method", method)]);
 }
 
 // Called by mongo_events
 pub fn observe_mongo_cmd(collection: &str, op: &str, dur_seconds: f64) {
-    if let Some(h) = MONGO_CMD_HISTO.get() {
+    if let Some(h) = metric(&MONGO_CMD_HISTO, "moniof_mongo_command_duration_seconds") {
+        h.with_label_values(&[collection, op]).observe(dur_seconds);
+    }
+    if let Some(s) = MONGO_CMD_SUMMARY.get() {
+        s.observe(&[collection, op], dur_seconds);
+    }
+    #[cfg(feature = "datadog")]
+    dogstatsd::histogram(
+        "mongo.command_duration_seconds",
+        dur_seconds,
+        &[("collection", collection), ("op", op)],
+    );
+}
+
+/// Like `observe_mongo_cmd`, for a (collection, op)'s first command in the
+/// process (`track_cold_commands`).
+pub fn observe_mongo_cold_cmd(collection: &str, op: &str, dur_seconds: f64) {
+    if let Some(h) = metric(&MONGO_COLD_HISTO, "moniof_mongo_cold_command_seconds") {
         h.with_label_values(&[collection, op]).observe(dur_seconds);
     }
+    #[cfg(feature = "datadog")]
+    dogstatsd::histogram(
+        "mongo.cold_command_seconds",
+        dur_seconds,
+        &[("collection", collection), ("op", op)],
+    );
+}
+
+/// A retryable write was re-sent by the driver (see `MOFMongoEvents`).
+pub fn inc_mongo_retry(collection: &str, op: &str) {
+    if let Some(c) = metric(&MONGO_RETRIES, "moniof_mongo_retries_total") {
+        c.with_label_values(&[collection, op]).inc();
+    }
+    #[cfg(feature = "datadog")]
+    dogstatsd::count("mongo.retries", &[("collection", collection), ("op", op)]);
+}
+
+/// Estimated serialized size of one Mongo reply.
+pub fn observe_mongo_reply_bytes(collection: &str, op: &str, bytes: u64) {
+    if let Some(h) = metric(&MONGO_REPLY_BYTES_HISTO, "moniof_mongo_reply_bytes") {
+        h.with_label_values(&[collection, op]).observe(bytes as f64);
+    }
+    #[cfg(feature = "datadog")]
+    dogstatsd::histogram(
+        "mongo.reply_bytes",
+        bytes as f64,
+        &[("collection", collection), ("op", op)],
+    );
+}
+
+pub fn observe_mongo_pool_wait(outcome: &str, dur_seconds: f64) {
+    if let Some(h) = metric(&MONGO_POOL_WAIT_HISTO, "moniof_mongo_pool_wait_seconds") {
+        h.with_label_values(&[outcome]).observe(dur_seconds);
+    }
+    #[cfg(feature = "datadog")]
+    dogstatsd::histogram("mongo.pool_wait_seconds", dur_seconds, &[("outcome", outcome)]);
+}
+
+// Called by sql_events
+pub fn observe_sql_cmd(table: &str, op: &str, dur_seconds: f64) {
+    if let Some(h) = metric(&SQL_CMD_HISTO, "moniof_sql_command_duration_seconds") {
+        h.with_label_values(&[table, op]).observe(dur_seconds);
+    }
+    #[cfg(feature = "datadog")]
+    dogstatsd::histogram("sql.command_duration_seconds", dur_seconds, &[("table", table), ("op", op)]);
+}
+
+/// Queries issued by one request, labeled by its dimension value. Past
+/// `max_values` distinct values, new ones are reported as `"other"`.
+pub fn observe_request_queries(dimension: Option<&str>, total: usize, max_values: usize) {
+    let label = match dimension {
+        None => "unset",
+        Some(v) if DIMENSION_VALUES.contains_key(v) => v,
+        Some(v) if DIMENSION_VALUES.len() < max_values => {
+            DIMENSION_VALUES.insert(v.to_string(), ());
+            v
+        }
+        Some(_) => "other",
+    };
+    if let Some(h) = metric(&REQUEST_QUERIES_HISTO, "moniof_request_queries") {
+        h.with_label_values(&[label]).observe(total as f64);
+    }
+    #[cfg(feature = "datadog")]
+    dogstatsd::histogram("request.queries", total as f64, &[("dimension", label)]);
+}
+
+// Called by instrument_job
+pub fn observe_job_queries(job: &str, total: usize) {
+    if let Some(h) = metric(&JOB_QUERIES_HISTO, "moniof_job_queries") {
+        h.with_label_values(&[job]).observe(total as f64);
+    }
+    #[cfg(feature = "datadog")]
+    dogstatsd::histogram("job.queries", total as f64, &[("job", job)]);
+}
+
+// Called by alert dispatch
+pub fn inc_alert_sent(dry_run: bool) {
+    let dry_run = if dry_run { "true" } else { "false" };
+    if let Some(c) = metric(&ALERTS_SENT, "moniof_alerts_sent_total") {
+        c.with_label_values(&[dry_run]).inc();
+    }
+    #[cfg(feature = "datadog")]
+    dogstatsd::count("alerts.sent", &[("dry_run", dry_run)]);
+}
+
+// Called by the SQLx pool sampler
+pub fn set_sqlx_pool(pool: &str, connections: u32, idle: usize) {
+    if let Some(g) = metric(&SQLX_POOL_CONNS, "moniof_sqlx_pool_connections") {
+        g.with_label_values(&[pool]).set(connections as i64);
+    }
+    if let Some(g) = metric(&SQLX_POOL_IDLE, "moniof_sqlx_pool_idle") {
+        g.with_label_values(&[pool]).set(idle as i64);
+    }
+    #[cfg(feature = "datadog")]
+    {
+        dogstatsd::gauge("sqlx.pool_connections", connections as i64, &[("pool", pool)]);
+        dogstatsd::gauge("sqlx.pool_idle", idle as i64, &[("pool", pool)]);
+    }
+}
+
+/// Drop a pool's gauges once it's no longer sampled.
+pub fn clear_sqlx_pool(pool: &str) {
+    for cell in [&SQLX_POOL_CONNS, &SQLX_POOL_IDLE] {
+        if let Some(g) = cell.get() {
+            let _ = g.remove_label_values(&[pool]);
+        }
+    }
+}
+
+pub fn inc_sql_error() {
+    if let Some(c) = metric(&SQL_ERRORS, "moniof_sql_errors_total") { c.inc(); }
+    #[cfg(feature = "datadog")]
+    dogstatsd::count("sql.errors", &[]);
 }
 
 pub async fn metrics_handler() -> HttpResponse {
@@ -95,6 +505,76 @@ pub async fn metrics_handler() -> HttpResponse {
     encode(registry)
 }
 
+/// Call count and cumulative duration for one collection / table.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CommandTotals {
+    pub count: u64,
+    pub total_seconds: f64,
+}
+
+/// Current aggregate values, shaped for humans (see [`json_metrics_handler`]).
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MetricsSnapshot {
+    pub inflight_requests: i64,
+    /// `"2xx"`, `"4xx"`, ... → request count.
+    pub requests_by_status_class: BTreeMap<String, u64>,
+    /// Collection → totals over all ops.
+    pub mongo: BTreeMap<String, CommandTotals>,
+    /// Table → totals over all ops.
+    pub sql: BTreeMap<String, CommandTotals>,
+    pub sql_errors: u64,
+}
+
+fn label<'a>(m: &'a prometheus::proto::Metric, name: &str) -> &'a str {
+    m.get_label()
+        .iter()
+        .find(|l| l.name() == name)
+        .map(|l| l.value())
+        .unwrap_or("unknown")
+}
+
+/// Per-first-label totals of a `(name, op)` command histogram.
+fn command_totals(h: Option<&HistogramVec>, by: &str) -> BTreeMap<String, CommandTotals> {
+    let mut out = BTreeMap::<String, CommandTotals>::new();
+    for mf in h.map(|h| h.collect()).unwrap_or_default() {
+        for m in mf.get_metric() {
+            let e = out.entry(label(m, by).to_string()).or_default();
+            e.count += m.get_histogram().get_sample_count();
+            e.total_seconds += m.get_histogram().get_sample_sum();
+        }
+    }
+    out
+}
+
+/// Read the collectors' current values.
+pub fn metrics_snapshot() -> MetricsSnapshot {
+    let mut by_class = BTreeMap::<String, u64>::new();
+    for mf in HTTP_REQ_COUNTER.get().map(|c| c.collect()).unwrap_or_default() {
+        for m in mf.get_metric() {
+            let class = match label(m, "status").chars().next() {
+                Some(c @ '1'..='5') => format!("{c}xx"),
+                _ => "other".to_string(),
+            };
+            *by_class.entry(class).or_insert(0) += m.get_counter().value() as u64;
+        }
+    }
+
+    MetricsSnapshot {
+        inflight_requests: HTTP_INFLIGHT.get().map(|g| g.get()).unwrap_or(0),
+        requests_by_status_class: by_class,
+        mongo: command_totals(MONGO_CMD_HISTO.get(), "collection"),
+        sql: command_totals(SQL_CMD_HISTO.get(), "table"),
+        sql_errors: SQL_ERRORS.get().map(|c| c.get()).unwrap_or(0),
+    }
+}
+
+/// [`MetricsSnapshot`] as JSON, for debug pages and humans. Not a scrape
+/// target: the shape may change and it drops most labels; use
+/// [`metrics_handler`] for machines.
+pub async fn json_metrics_handler() -> HttpResponse {
+    HttpResponse::Ok().json(metrics_snapshot())
+}
+
 fn encode(registry: &Registry) -> HttpResponse {
     let encoder = TextEncoder::new();
     let mf = registry.gather();
@@ -106,3 +586,30 @@ fn encode(registry: &Registry) -> HttpResponse {
         .content_type(encoder.format_type())
         .body(buf)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The only test here that touches the process-wide collectors: it must
+    // see them before anything else calls `init_prometheus`.
+    #[test]
+    fn init_skips_a_clashing_collector_and_installs_the_rest() {
+        let registry = REGISTRY.get_or_init(new_registry);
+        let taken = IntCounter::new("moniof_http_requests_total", "registered by the app").unwrap();
+        registry.register(Box::new(taken)).unwrap();
+
+        init_prometheus();
+
+        assert!(HTTP_REQ_COUNTER.get().is_none());
+        assert!(HTTP_INFLIGHT.get().is_some());
+        assert!(HTTP_REQ_HISTO.get().is_some());
+        assert!(DB_TOTAL_HISTO.get().is_some());
+
+        // the clashing metric is a no-op, the others still record
+        observe_http("GET", 200, 0.01);
+        assert!(metrics_snapshot().requests_by_status_class.is_empty());
+        let histo = HTTP_REQ_HISTO.get().unwrap().with_label_values(&["GET"]);
+        assert_eq!(histo.get_sample_count(), 1);
+    }
+}
@@ -1,5 +1,25 @@
+use futures_util::future::BoxFuture;
+use once_cell::sync::Lazy;
 use reqwest::Client;
 use serde::Serialize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// Notifications spawned but not yet finished (sent, failed or cancelled).
+static PENDING: AtomicUsize = AtomicUsize::new(0);
+static DRAINED: Lazy<Notify> = Lazy::new(Notify::new);
+
+/// Decrements `PENDING` when the notify task ends, however it ends.
+struct PendingGuard;
+
+impl Drop for PendingGuard {
+    fn drop(&mut self) {
+        if PENDING.fetch_sub(1, Ordering::AcqRel) == 1 {
+            DRAINED.notify_waiters();
+        }
+    }
+}
 
 #[derive(Serialize)]
 struct SlackPayload<'a> {
@@ -26,3 +46,36 @@ pub async fn notify(webhook_url: Option<String>, text: String) {
         );
     }
 }
+
+/// Run an alert delivery in the background, tracked so `flush_alerts` can
+/// wait for it.
+pub(crate) fn spawn_delivery(fut: BoxFuture<'static, ()>) {
+    PENDING.fetch_add(1, Ordering::AcqRel);
+    let guard = PendingGuard;
+    tokio::spawn(async move {
+        let _guard = guard;
+        fut.await;
+    });
+}
+
+/// Wait (up to `timeout`) for in-flight alerts (Slack or a custom
+/// `AlertSink`) to be delivered.
+///
+/// Await this during graceful shutdown, before the runtime (or actix's
+/// workers) stop: tasks still pending when their runtime drops are lost.
+/// Returns `false` if alerts were still pending at the timeout.
+pub async fn flush_alerts(timeout: Duration) -> bool {
+    let drained = async {
+        loop {
+            let notified = DRAINED.notified();
+            tokio::pin!(notified);
+            // register before checking, so a wake-up in between isn't missed
+            notified.as_mut().enable();
+            if PENDING.load(Ordering::Acquire) == 0 {
+                return;
+            }
+            notified.await;
+        }
+    };
+    tokio::time::timeout(timeout, drained).await.is_ok()
+}
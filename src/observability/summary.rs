@@ -0,0 +1,136 @@
+// Server-side quantiles (`moniof_mongo_command_quantiles`). The prometheus
+// crate has no Summary collector, so this is a minimal one: per label set, a
+// ring buffer of the latest samples for the quantiles, plus cumulative
+// count and sum.
+
+use dashmap::DashMap;
+use prometheus::core::{Collector, Desc};
+use prometheus::proto::{LabelPair, Metric, MetricFamily, MetricType, Quantile, Summary};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Latest samples kept per label set; quantiles cover this window only.
+const WINDOW: usize = 1024;
+/// Label sets beyond this are not tracked.
+const MAX_SERIES: usize = 10_000;
+
+#[derive(Default)]
+struct Window {
+    samples: Vec<f64>,
+    next: usize,
+    count: u64,
+    sum: f64,
+}
+
+impl Window {
+    fn push(&mut self, v: f64) {
+        if self.samples.len() < WINDOW {
+            self.samples.push(v);
+        } else {
+            self.samples[self.next] = v;
+        }
+        self.next = (self.next + 1) % WINDOW;
+        self.count += 1;
+        self.sum += v;
+    }
+
+    fn summary(&self, objectives: &[f64]) -> Summary {
+        let mut sorted = self.samples.clone();
+        sorted.sort_unstable_by(f64::total_cmp);
+
+        let quantiles = objectives
+            .iter()
+            .map(|&q| {
+                let mut quantile = Quantile::default();
+                quantile.set_quantile(q);
+                if let Some(last) = sorted.len().checked_sub(1) {
+                    let rank = (sorted.len() as f64 * q).ceil() as usize;
+                    quantile.set_value(sorted[rank.saturating_sub(1).min(last)]);
+                }
+                quantile
+            })
+            .collect();
+
+        let mut summary = Summary::default();
+        summary.set_sample_count(self.count);
+        summary.set_sample_sum(self.sum);
+        summary.set_quantile(quantiles);
+        summary
+    }
+}
+
+struct Inner {
+    desc: Desc,
+    label_names: Vec<String>,
+    objectives: Vec<f64>,
+    series: DashMap<Vec<String>, Window>,
+}
+
+/// Summary with variable labels, reporting `objectives` (e.g. `0.5`, `0.99`)
+/// over the latest samples of each label set.
+#[derive(Clone)]
+pub(crate) struct SummaryVec(Arc<Inner>);
+
+impl SummaryVec {
+    pub(crate) fn new(
+        name: &str,
+        help: &str,
+        label_names: &[&str],
+        objectives: Vec<f64>,
+    ) -> prometheus::Result<Self> {
+        let label_names: Vec<String> = label_names.iter().map(|n| n.to_string()).collect();
+        let desc = Desc::new(name.to_string(), help.to_string(), label_names.clone(), HashMap::new())?;
+        Ok(Self(Arc::new(Inner { desc, label_names, objectives, series: DashMap::new() })))
+    }
+
+    pub(crate) fn observe(&self, label_values: &[&str], v: f64) {
+        let key: Vec<String> = label_values.iter().map(|v| v.to_string()).collect();
+        if let Some(mut w) = self.0.series.get_mut(&key) {
+            w.push(v);
+            return;
+        }
+        if self.0.series.len() < MAX_SERIES {
+            self.0.series.entry(key).or_default().push(v);
+        }
+    }
+}
+
+impl Collector for SummaryVec {
+    fn desc(&self) -> Vec<&Desc> {
+        vec![&self.0.desc]
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        let metrics = self
+            .0
+            .series
+            .iter()
+            .map(|e| {
+                let labels = self
+                    .0
+                    .label_names
+                    .iter()
+                    .zip(e.key())
+                    .map(|(name, value)| {
+                        let mut pair = LabelPair::default();
+                        pair.set_name(name.clone());
+                        pair.set_value(value.clone());
+                        pair
+                    })
+                    .collect();
+
+                let mut m = Metric::default();
+                m.set_label(labels);
+                m.set_summary(e.value().summary(&self.0.objectives));
+                m
+            })
+            .collect();
+
+        let mut mf = MetricFamily::default();
+        mf.set_name(self.0.desc.fq_name.clone());
+        mf.set_help(self.0.desc.help.clone());
+        mf.set_field_type(MetricType::SUMMARY);
+        mf.set_metric(metrics);
+        vec![mf]
+    }
+}
@@ -0,0 +1,151 @@
+// src/services/axum.rs
+#![cfg(feature = "axum")]
+
+use crate::config::MoniOFConfig;
+use crate::observability::prom;
+use crate::services::lifecycle::{RequestLifecycle, RequestMeta, ResponseMeta, header_write_failed};
+
+use axum::extract::MatchedPath;
+use futures_util::future::BoxFuture;
+use http::header::{HeaderName, HeaderValue};
+use http::{Request, Response};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// axum / tower counterpart of the actix [`MoniOF`](crate::MoniOF) middleware.
+///
+/// ```ignore
+/// let app = Router::new()
+///     .route("/users/{id}", get(handler))
+///     .layer(moniof::MoniOFLayer::new());
+/// ```
+///
+/// Route overrides match axum's `MatchedPath`, which is only set when the
+/// layer runs after routing (`Router::route_layer`, or `layer` on a router
+/// whose routes are already added).
+#[derive(Clone)]
+pub struct MoniOFLayer {
+    cfg: Arc<MoniOFConfig>,
+    route_overrides: Arc<HashMap<String, MoniOFConfig>>,
+}
+
+impl Default for MoniOFLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MoniOFLayer {
+    pub fn new() -> Self {
+        Self::with_config(MoniOFConfig::default())
+    }
+
+    pub fn with_config(cfg: MoniOFConfig) -> Self {
+        prom::init_prometheus();
+        Self {
+            cfg: Arc::new(cfg),
+            route_overrides: Arc::new(HashMap::new()),
+        }
+    }
+
+    /// Use `cfg` instead of the base config for requests whose matched route
+    /// pattern is `pattern` (as registered, e.g. `"/dashboard/{id}"`).
+    pub fn with_route_override(mut self, pattern: impl Into<String>, cfg: MoniOFConfig) -> Self {
+        Arc::make_mut(&mut self.route_overrides).insert(pattern.into(), cfg);
+        self
+    }
+}
+
+impl<S> Layer<S> for MoniOFLayer {
+    type Service = MoniOFService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MoniOFService {
+            inner,
+            cfg: self.cfg.clone(),
+            route_overrides: self.route_overrides.clone(),
+        }
+    }
+}
+
+impl<B> RequestMeta for Request<B> {
+    fn method(&self) -> &str {
+        self.method().as_str()
+    }
+
+    fn path(&self) -> &str {
+        self.uri().path()
+    }
+
+    fn route(&self) -> Option<String> {
+        self.extensions().get::<MatchedPath>().map(|p| p.as_str().to_string())
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers().get(name).and_then(|v| v.to_str().ok())
+    }
+}
+
+impl<B> ResponseMeta for Response<B> {
+    fn status(&self) -> u16 {
+        self.status().as_u16()
+    }
+
+    fn insert_header(&mut self, name: &'static str, value: &str) {
+        match HeaderValue::from_str(value) {
+            Ok(hv) => {
+                self.headers_mut().insert(HeaderName::from_static(name), hv);
+            }
+            Err(_) => header_write_failed(name, value),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct MoniOFService<S> {
+    inner: S,
+    cfg: Arc<MoniOFConfig>,
+    route_overrides: Arc<HashMap<String, MoniOFConfig>>,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for MoniOFService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    ReqBody: Send + 'static,
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        // the clone is not ready yet; call the instance poll_ready was run on
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        // skipped paths (metrics, health checks): straight pass-through
+        let Some(lc) = RequestLifecycle::start(&self.cfg, &self.route_overrides, &req) else {
+            return Box::pin(inner.call(req));
+        };
+
+        // handlers can take `Option<Extension<QueryStatsHandle>>`
+        if let Some(h) = lc.stats_handle() {
+            req.extensions_mut().insert(h.clone());
+        }
+
+        Box::pin(async move {
+            let mut res = lc.run(|| inner.call(req)).await?;
+            lc.finish(&mut res);
+            Ok(res)
+        })
+    }
+}
@@ -0,0 +1,171 @@
+// src/services/grpc.rs
+#![cfg(feature = "tonic")]
+
+use crate::config::MoniOFConfig;
+use crate::observability::prom;
+use crate::services::lifecycle::{RequestLifecycle, RequestMeta, ResponseMeta, header_write_failed};
+
+use futures_util::future::BoxFuture;
+use http::header::{HeaderName, HeaderValue};
+use http::{Request, Response};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// gRPC (tonic) variant of the middleware, as a tower layer:
+///
+/// ```ignore
+/// Server::builder()
+///     .layer(moniof::MoniOFGrpcLayer::new())
+///     .add_service(MyServiceServer::new(svc))
+///     .serve(addr)
+///     .await?;
+/// ```
+///
+/// The RPC path (`/pkg.Service/Method`) is the route in logs and alerts, and
+/// the `grpc-status` code stands in for the HTTP status. The `method` metric
+/// label is the HTTP method (always `POST` for gRPC), so RPC paths don't
+/// multiply the `method`-labeled series. Route overrides and `skip_paths`
+/// match the RPC path.
+///
+/// Handlers can read the request's stats from the tonic request extensions:
+/// `request.extensions().get::<moniof::QueryStatsHandle>()`.
+///
+/// The status is read from the response headers, where tonic puts it for
+/// RPCs that fail before sending a message; streamed responses that fail
+/// later (status only in trailers) are reported as `0` (OK).
+#[derive(Clone)]
+pub struct MoniOFGrpcLayer {
+    cfg: Arc<MoniOFConfig>,
+    route_overrides: Arc<HashMap<String, MoniOFConfig>>,
+}
+
+impl Default for MoniOFGrpcLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MoniOFGrpcLayer {
+    pub fn new() -> Self {
+        Self::with_config(MoniOFConfig::default())
+    }
+
+    pub fn with_config(cfg: MoniOFConfig) -> Self {
+        prom::init_prometheus();
+        Self {
+            cfg: Arc::new(cfg),
+            route_overrides: Arc::new(HashMap::new()),
+        }
+    }
+
+    /// Use `cfg` instead of the base config for one RPC
+    /// (e.g. `"/users.UserService/ListUsers"`).
+    pub fn with_route_override(mut self, rpc_path: impl Into<String>, cfg: MoniOFConfig) -> Self {
+        Arc::make_mut(&mut self.route_overrides).insert(rpc_path.into(), cfg);
+        self
+    }
+}
+
+impl<S> Layer<S> for MoniOFGrpcLayer {
+    type Service = MoniOFGrpcService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MoniOFGrpcService {
+            inner,
+            cfg: self.cfg.clone(),
+            route_overrides: self.route_overrides.clone(),
+        }
+    }
+}
+
+/// RPC view of an HTTP/2 request: the path is the route.
+struct GrpcRequest<'a, B>(&'a Request<B>);
+
+impl<B> RequestMeta for GrpcRequest<'_, B> {
+    fn method(&self) -> &str {
+        self.0.method().as_str()
+    }
+
+    fn path(&self) -> &str {
+        self.0.uri().path()
+    }
+
+    fn route(&self) -> Option<String> {
+        Some(self.0.uri().path().to_string())
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.0.headers().get(name).and_then(|v| v.to_str().ok())
+    }
+}
+
+/// Reports the `grpc-status` code as the status.
+struct GrpcResponse<'a, B>(&'a mut Response<B>);
+
+impl<B> ResponseMeta for GrpcResponse<'_, B> {
+    fn status(&self) -> u16 {
+        self.0
+            .headers()
+            .get("grpc-status")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0)
+    }
+
+    fn insert_header(&mut self, name: &'static str, value: &str) {
+        match HeaderValue::from_str(value) {
+            Ok(hv) => {
+                self.0.headers_mut().insert(HeaderName::from_static(name), hv);
+            }
+            Err(_) => header_write_failed(name, value),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct MoniOFGrpcService<S> {
+    inner: S,
+    cfg: Arc<MoniOFConfig>,
+    route_overrides: Arc<HashMap<String, MoniOFConfig>>,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for MoniOFGrpcService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    ReqBody: Send + 'static,
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        // the clone is not ready yet; call the instance poll_ready was run on
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        let Some(lc) = RequestLifecycle::start(&self.cfg, &self.route_overrides, &GrpcRequest(&req)) else {
+            return Box::pin(inner.call(req));
+        };
+
+        // tonic handlers see it in `Request::extensions()`
+        if let Some(h) = lc.stats_handle() {
+            req.extensions_mut().insert(h.clone());
+        }
+
+        Box::pin(async move {
+            let mut res = lc.run(|| inner.call(req)).await?;
+            lc.finish(&mut GrpcResponse(&mut res));
+            Ok(res)
+        })
+    }
+}
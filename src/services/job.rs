@@ -0,0 +1,89 @@
+// Background jobs (cron workers, queue consumers) aren't behind any HTTP
+// middleware. `instrument_job` gives them the same task-local stats scope
+// and a per-job report: N+1 detection, a summary log, metrics and alerts.
+
+use crate::config::{MoniOFConfig, is_enabled};
+use crate::core::stats::QueryStatsHandle;
+use crate::core::task_ctx::MONIOF_HANDLE;
+use crate::observability::{of, prom};
+use crate::services::report::{self, Analysis};
+
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+/// Run a background job (`fut`) with its own stats scope, then report it
+/// under `name`, using the default [`MoniOFConfig`] thresholds.
+///
+/// ```ignore
+/// moniof::instrument_job("nightly-digest", send_digests(&db)).await;
+/// ```
+///
+/// See [`instrument_job_with`].
+pub async fn instrument_job<F: Future>(name: &str, fut: F) -> F::Output {
+    instrument_job_with(&MoniOFConfig::default(), name, fut).await
+}
+
+/// [`instrument_job`] with explicit thresholds.
+///
+/// Queries issued by `fut` (through the Mongo/SQL instrumentation, `mark`,
+/// `track`, ...) are counted like a request's. When it completes, the job
+/// logs a summary (target `moniof::job`), observes `moniof_job_queries{job}`,
+/// records N+1 suspects in the offenders leaderboard (route `job/<name>`)
+/// and, with `log_warnings`, warns and alerts like a request would.
+/// `sample_rate`, `skip_*` and header settings don't apply.
+pub async fn instrument_job_with<F: Future>(cfg: &MoniOFConfig, name: &str, fut: F) -> F::Output {
+    if !is_enabled() {
+        return fut.await;
+    }
+
+    let handle = if cfg.record_query_order {
+        QueryStatsHandle::with_event_log(cfg.query_order_max_events)
+    } else {
+        QueryStatsHandle::new()
+    };
+    handle.0.lock().max_distinct_keys = cfg.max_distinct_keys;
+
+    let started_at = Instant::now();
+    let out = MONIOF_HANDLE.scope(handle.clone(), fut).await;
+    finish_job(cfg, name, &handle, started_at.elapsed());
+    out
+}
+
+fn finish_job(cfg: &MoniOFConfig, name: &str, handle: &QueryStatsHandle, elapsed: Duration) {
+    // lock released here, before logging and alerting
+    let a = Analysis::new(&handle.0.lock(), cfg);
+    let elapsed_ms = elapsed.as_millis();
+    let route_key = format!("job/{}", name);
+
+    of::record_offenders(&a.suspects, Some(&route_key));
+    prom::observe_job_queries(name, a.total);
+
+    // warnings below carry the job name
+    let _enter = tracing::info_span!("moniof_job", job = name).entered();
+
+    tracing::info!(
+        target: "moniof::job",
+        job = name,
+        total = a.total,
+        reads = a.reads,
+        writes = a.writes,
+        db_total_ms = a.db_total_ms,
+        db_busy_ms = a.db_busy_ms as u64,
+        elapsed_ms = elapsed_ms as u64,
+        n_plus_one_suspects = a.suspects.len(),
+        "job summary"
+    );
+
+    if cfg.log_warnings {
+        report::warn_and_alert(cfg, &a, elapsed.as_secs_f64(), &route_key, || {
+            vec![
+                "⚠️ *moniOF job alert*".to_string(),
+                format!("• job: `{}`", name),
+                format!("• total queries: {} ({} reads / {} writes)", a.total, a.reads, a.writes),
+                format!("• elapsed: {} ms", elapsed_ms),
+                format!("• db total latency: {} ms", a.db_total_ms),
+                format!("• db busy time: {} ms", a.db_busy_ms),
+            ]
+        });
+    }
+}
@@ -0,0 +1,689 @@
+// Framework-independent request lifecycle: config selection, sampling,
+// request IDs, the task-local stats scope and the post-request report
+// (metrics, headers, warnings, Slack, hook). The actix and axum middlewares
+// are thin adapters that implement `RequestMeta` / `ResponseMeta`.
+
+use crate::config::{MoniOFConfig, global, is_enabled, is_strict};
+use crate::core::stats::{QueryKind, QueryStatsHandle};
+use crate::core::task_ctx::{MONIOF_HANDLE, current_stats};
+use crate::observability::{prom, of};
+use crate::observability::of::OfSuspect;
+use crate::services::report::{self, Analysis};
+
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Once;
+use std::time::Instant;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tracing::{self, Instrument};
+
+/// What the lifecycle needs to know about an incoming request.
+pub trait RequestMeta {
+    fn method(&self) -> &str;
+    fn path(&self) -> &str;
+    /// Matched route pattern (e.g. `/users/{id}`), if the framework knows it.
+    fn route(&self) -> Option<String>;
+    fn header(&self, name: &str) -> Option<&str>;
+}
+
+/// What the lifecycle needs from the outgoing response.
+pub trait ResponseMeta {
+    fn status(&self) -> u16;
+    /// Set a response header; invalid values may be ignored.
+    fn insert_header(&mut self, name: &'static str, value: &str);
+}
+
+/// One tracked request, from `start` to `finish`:
+///
+/// ```ignore
+/// let Some(lc) = RequestLifecycle::start(&cfg, &overrides, &req) else {
+///     return inner.call(req).await; // skipped path
+/// };
+/// let mut res = lc.run(|| inner.call(req)).await?;
+/// lc.finish(&mut res);
+/// ```
+pub struct RequestLifecycle {
+    cfg: MoniOFConfig,
+    info: RequestInfo,
+    /// `None` when the request was not sampled.
+    handle: Option<QueryStatsHandle>,
+    /// Decremented on drop: also on error, panic or cancellation.
+    inflight: prom::InflightGuard,
+    started_at: Instant,
+    /// `moniof.request` span (`request_span` config, sampled requests only).
+    span: Option<tracing::Span>,
+}
+
+impl RequestLifecycle {
+    /// Pick the effective config (route override, else `base`) and start
+    /// tracking. `None` for `skip_paths` / `skip_methods`, while the global `enabled` kill
+    /// switch is off, or when an outer middleware already tracks the request:
+    /// pass the request straight through.
+    pub fn start(
+        base: &MoniOFConfig,
+        route_overrides: &HashMap<String, MoniOFConfig>,
+        req: &impl RequestMeta,
+    ) -> Option<Self> {
+        if !is_enabled() {
+            return None;
+        }
+
+        // nested middlewares (e.g. a global and a scoped `MoniOF`): a fresh
+        // scope here would hide the queries from the outer one, which would
+        // then report zero. Let the outermost scope collect everything.
+        if current_stats().is_some() {
+            warn_nested_once();
+            return None;
+        }
+
+        let matched = req.route();
+        let cfg = matched
+            .as_deref()
+            .and_then(|r| route_overrides.get(r))
+            .unwrap_or(base);
+        // no route pattern (dynamic routing, 404): label by normalized path
+        let route = matched.or_else(|| {
+            cfg.path_normalizer.as_ref().map(|n| n.normalize(req.path()))
+        });
+
+        if cfg.is_skipped_path(req.path()) || cfg.is_skipped_method(req.method()) {
+            return None;
+        }
+
+        let inflight = prom::InflightGuard::new();
+        let started_at = Instant::now();
+
+        let handle = sampled(cfg.sample_rate).then(|| {
+            let h = if cfg.record_query_order {
+                QueryStatsHandle::with_event_log(cfg.query_order_max_events)
+            } else {
+                QueryStatsHandle::new()
+            };
+            h.0.lock().max_distinct_keys = cfg.max_distinct_keys;
+            h
+        });
+        // the ID is only used by the full report
+        let request_id = if handle.is_some() {
+            request_id(req.header("x-request-id"))
+        } else {
+            String::new()
+        };
+
+        let span = (cfg.request_span && handle.is_some()).then(|| {
+            tracing::info_span!(
+                "moniof.request",
+                method = %req.method(),
+                route = route.as_deref().unwrap_or("unmatched"),
+                request_id = %request_id,
+                status = tracing::field::Empty,
+                total = tracing::field::Empty,
+                db_total_ms = tracing::field::Empty,
+            )
+        });
+
+        Some(Self {
+            cfg: cfg.clone(),
+            info: RequestInfo {
+                method: req.method().to_string(),
+                route,
+                request_id,
+            },
+            handle,
+            inflight,
+            started_at,
+            span,
+        })
+    }
+
+    /// This request's stats (`None` when not sampled), e.g. to expose to
+    /// handlers through the framework's request extensions.
+    pub fn stats_handle(&self) -> Option<&QueryStatsHandle> {
+        self.handle.as_ref()
+    }
+
+    /// Call the inner service (`call`, e.g. `|| svc.call(req)`) with this
+    /// request's stats installed as the task-local context, so `mark` /
+    /// `track` / the DB hooks see it, including work done inside `call`.
+    pub async fn run<F, Fut>(&self, call: F) -> Fut::Output
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future,
+    {
+        let Some(h) = &self.handle else {
+            return call().await;
+        };
+        let scoped = MONIOF_HANDLE.scope(h.clone(), async move { call().await });
+        match &self.span {
+            // DB instrumentation events nest under the request span
+            Some(span) => scoped.instrument(span.clone()).await,
+            None => scoped.await,
+        }
+    }
+
+    /// Observe metrics and, for sampled requests, report (headers, warnings,
+    /// Slack, `on_request_complete`).
+    pub fn finish(self, res: &mut impl ResponseMeta) {
+        let req_duration_s = self.started_at.elapsed().as_secs_f64();
+        drop(self.inflight);
+
+        #[cfg(feature = "sqlx")]
+        if is_strict() {
+            check_sql_layer();
+        }
+
+        let status = res.status();
+        let Some(handle) = self.handle else {
+            // not sampled: only the cheap HTTP counter/histogram
+            prom::observe_http(&self.info.method, status, req_duration_s);
+            return;
+        };
+
+        // fast request: metrics only, skip the per-key analysis
+        if let Some(th) = self.cfg.analyze_only_above_ms {
+            if self.started_at.elapsed().as_millis() < th {
+                let db_by_kind = kind_seconds(&handle.0.lock().latency_by_kind());
+                prom::observe_request(&self.info.method, status, req_duration_s, &db_by_kind);
+                return;
+            }
+        }
+
+        let headers = finish_request(
+            &self.cfg,
+            &handle,
+            &self.info,
+            status,
+            req_duration_s,
+            self.span.as_ref(),
+        );
+        for (name, val) in headers {
+            res.insert_header(name, &val);
+        }
+    }
+}
+
+/// A `ResponseMeta::insert_header` implementation couldn't write a header
+/// (`value` isn't a valid header value). Only reported in `strict` mode.
+pub fn header_write_failed(name: &str, value: &str) {
+    if is_strict() {
+        tracing::error!(
+            target = "moniof",
+            header = name,
+            value = value,
+            "strict: response header not written (invalid value)"
+        );
+    }
+}
+
+/// Strict mode: the `sqlx` feature is on but `MOFSqlEvents` isn't part of
+/// any dispatcher, so SQL queries go uncounted. Reported once.
+#[cfg(feature = "sqlx")]
+fn check_sql_layer() {
+    use std::sync::atomic::Ordering;
+
+    static CHECKED: Once = Once::new();
+    CHECKED.call_once(|| {
+        if !crate::instrumentation::sql_events::SQL_LAYER_REGISTERED.load(Ordering::Relaxed) {
+            tracing::error!(
+                target = "moniof",
+                "strict: the sqlx feature is enabled but MOFSqlEvents isn't registered with \
+                 any tracing subscriber; SQL queries are not counted. Add moniof::moniof_layer()"
+            );
+        }
+    });
+}
+
+fn warn_nested_once() {
+    static WARNED: Once = Once::new();
+    WARNED.call_once(|| {
+        tracing::warn!(
+            target = "moniof",
+            "nested moniof middleware: the request is already tracked by an outer one; \
+             the inner middleware passes through and its config is ignored"
+        );
+    });
+}
+
+/// Read-only per-request snapshot handed to `on_request_complete` and
+/// `summary_sink`.
+#[derive(Clone, Debug)]
+pub struct RequestSummary {
+    pub request_id: String,
+    pub method: String,
+    /// Matched route pattern (e.g. `/users/{id}`), if any.
+    pub route: Option<String>,
+    pub status: u16,
+    pub total: usize,
+    pub reads: usize,
+    pub writes: usize,
+    pub db_total_ms: u128,
+    pub elapsed_ms: i128,
+    /// Latency of the first query (ms), which often includes warmup.
+    pub first_query_ms: Option<u128>,
+    /// `elapsed_ms - db_total_ms`, clamped at 0.
+    pub app_ms: u128,
+    /// DB time exceeded wall time (queries ran concurrently), so `app_ms`
+    /// was clamped.
+    pub db_concurrent: bool,
+    /// Wall time (ms) with at least one query in flight; see
+    /// `QueryStats::db_busy_ms`.
+    pub db_busy_ms: u128,
+    /// Time (ms) in `measure` blocks; part of `app_ms`.
+    pub work_ms: u128,
+    /// Estimated bytes of all DB replies (Mongo only today).
+    pub reply_bytes: u64,
+    /// Key whose replies added up to the most bytes.
+    pub heaviest_reply_key: Option<String>,
+    pub suspects: Vec<OfSuspect>,
+}
+
+/// `x-moniof-slow-<n>-key` / `-latency-ms` header names (see
+/// `slowest_key_count`); header names must be static.
+const SLOW_KEY_HEADERS: [(&str, &str); 10] = [
+    ("x-moniof-slow-1-key", "x-moniof-slow-1-latency-ms"),
+    ("x-moniof-slow-2-key", "x-moniof-slow-2-latency-ms"),
+    ("x-moniof-slow-3-key", "x-moniof-slow-3-latency-ms"),
+    ("x-moniof-slow-4-key", "x-moniof-slow-4-latency-ms"),
+    ("x-moniof-slow-5-key", "x-moniof-slow-5-latency-ms"),
+    ("x-moniof-slow-6-key", "x-moniof-slow-6-latency-ms"),
+    ("x-moniof-slow-7-key", "x-moniof-slow-7-latency-ms"),
+    ("x-moniof-slow-8-key", "x-moniof-slow-8-latency-ms"),
+    ("x-moniof-slow-9-key", "x-moniof-slow-9-latency-ms"),
+    ("x-moniof-slow-10-key", "x-moniof-slow-10-latency-ms"),
+];
+
+/// Request metadata captured before the inner service runs.
+struct RequestInfo {
+    method: String,
+    route: Option<String>,
+    request_id: String,
+}
+
+thread_local! {
+    static RNG: Cell<u64> = Cell::new({
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        let local = 0u8;
+        // mix in a per-thread address so threads don't share a sequence
+        (nanos ^ (&local as *const u8 as u64)) | 1
+    });
+}
+
+/// Fast non-cryptographic random u64 (thread-local xorshift, no extra deps).
+fn next_rand() -> u64 {
+    RNG.with(|r| {
+        let mut x = r.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        r.set(x);
+        x
+    })
+}
+
+/// Per-request sampling decision.
+fn sampled(rate: f64) -> bool {
+    if rate >= 1.0 {
+        return true;
+    }
+    if rate <= 0.0 {
+        return false;
+    }
+
+    // top 53 bits -> uniform f64 in [0, 1)
+    ((next_rand() >> 11) as f64 / (1u64 << 53) as f64) < rate
+}
+
+/// Longest incoming `X-Request-Id` kept; it's echoed into logs, the
+/// response header and Slack alerts.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Incoming `X-Request-Id` value, or a fresh UUIDv4-shaped ID when absent.
+/// Generated IDs are for log correlation only, not for security.
+///
+/// The incoming value is client-controlled: it's capped, made printable, and
+/// stripped of backticks so it can't break out of the alert's code span.
+fn request_id(incoming: Option<&str>) -> String {
+    let incoming = incoming.map(|v| header_value(&v.replace('`', ""), MAX_REQUEST_ID_LEN));
+    if let Some(id) = incoming.filter(|v| !v.trim().is_empty()) {
+        return id;
+    }
+
+    let hi = next_rand();
+    let lo = next_rand();
+    format!(
+        "{:08x}-{:04x}-4{:03x}-{:04x}-{:012x}",
+        hi >> 32,
+        (hi >> 16) & 0xffff,
+        hi & 0x0fff,
+        ((lo >> 48) & 0x3fff) | 0x8000,
+        lo & 0xffff_ffff_ffff
+    )
+}
+
+/// Make `raw` (a query key, possibly with odd bytes) a valid header value,
+/// so the header isn't silently dropped: anything outside visible ASCII
+/// becomes `?`, and values longer than `max_len` bytes end in `...`.
+fn header_value(raw: &str, max_len: usize) -> String {
+    let mut out: String = raw
+        .chars()
+        .map(|c| if c == ' ' || c.is_ascii_graphic() { c } else { '?' })
+        .collect();
+    if max_len > 0 && out.len() > max_len {
+        // all ASCII now, so any byte index is a char boundary
+        if max_len < 3 {
+            out.truncate(max_len);
+        } else {
+            out.truncate(max_len - 3);
+            out.push_str("...");
+        }
+    }
+    out
+}
+
+/// `users=40,orders=3,...` for the top `n` collections; whole entries are
+/// dropped from the end to stay within `max_len` (0 = no limit).
+fn collection_counts_value(counts: &[(String, usize)], n: usize, max_len: usize) -> String {
+    let mut out = String::new();
+    for (coll, count) in counts.iter().take(n) {
+        let entry = format!("{}{}={}", if out.is_empty() { "" } else { "," }, coll, count);
+        if max_len > 0 && out.len() + entry.len() > max_len {
+            break;
+        }
+        out.push_str(&entry);
+    }
+    out
+}
+
+/// Hand `summary` to `summary_sink` without blocking the response. A full
+/// channel drops it; a closed one is reported once.
+fn send_summary(sink: &mpsc::Sender<RequestSummary>, summary: RequestSummary) {
+    match sink.try_send(summary) {
+        Ok(()) => {}
+        Err(TrySendError::Full(s)) => tracing::debug!(
+            target = "moniof",
+            request_id = %s.request_id,
+            "summary_sink is full; request summary dropped"
+        ),
+        Err(TrySendError::Closed(_)) => {
+            static WARNED: Once = Once::new();
+            WARNED.call_once(|| {
+                tracing::warn!(
+                    target = "moniof",
+                    "summary_sink receiver was dropped; request summaries are discarded"
+                );
+            });
+        }
+    }
+}
+
+/// Per-kind DB latency in seconds, labeled for metrics.
+fn kind_seconds(db_by_kind: &[(QueryKind, u128)]) -> Vec<(&'static str, f64)> {
+    db_by_kind
+        .iter()
+        .map(|(kind, ms)| (kind.as_str(), (*ms as f64) / 1000.0))
+        .collect()
+}
+
+/// Analyze the finished request's stats: observe metrics, log warnings,
+/// alert Slack, run `on_request_complete` / `summary_sink`. Returns the `x-moniof-*` headers
+/// to add (empty unless `add_response_headers`).
+fn finish_request(
+    cfg: &MoniOFConfig,
+    handle: &QueryStatsHandle,
+    info: &RequestInfo,
+    status: u16,
+    req_duration_s: f64,
+    request_span: Option<&tracing::Span>,
+) -> Vec<(&'static str, String)> {
+    let RequestInfo { method, route, request_id } = info;
+
+    // --------------------------
+    // Read stats for this request
+    // --------------------------
+    // the guard is a temporary: the lock is released before any metrics,
+    // logging, alerting or user hook below
+    let a = Analysis::new(&handle.0.lock(), cfg);
+    let slowest_key = a.slowest_key();
+
+    of::record_offenders(&a.suspects, route.as_deref());
+
+    let db_by_kind_s = kind_seconds(&a.db_by_kind);
+    prom::observe_request(method, status, req_duration_s, &db_by_kind_s);
+    prom::observe_app_time(method, (a.app_ms as f64) / 1000.0);
+    prom::observe_db_busy(method, (a.db_busy_ms as f64) / 1000.0);
+    prom::observe_db_distinct_keys(method, a.distinct_keys);
+
+    // query fan-out, labeled per tenant (or other dimension) when configured
+    let g = global();
+    let dimension = g.query_count_dimension.as_deref().and_then(|dim| a.dimension(dim));
+    prom::observe_request_queries(dimension, a.total, g.max_dimension_values);
+
+    #[cfg(feature = "otel")]
+    crate::observability::otel::record_request(&crate::observability::otel::OtelRequest {
+        request_id,
+        method,
+        route: route.as_deref(),
+        status,
+        duration_s: req_duration_s,
+        total: a.total,
+        db_total_ms: a.db_total_ms,
+        db_by_kind: &db_by_kind_s,
+        slowest_key: slowest_key.map(|(k, _)| k.as_str()),
+        n_plus_one_suspects: a.suspects.len(),
+    });
+
+    // every log line below carries the request ID: inside the
+    // `moniof.request` span when enabled, else a lightweight one
+    let span = match request_span {
+        Some(span) => {
+            span.record("status", status);
+            span.record("total", a.total);
+            span.record("db_total_ms", a.db_total_ms as u64);
+            span.clone()
+        }
+        None => tracing::info_span!("moniof_request", request_id = %request_id),
+    };
+    let _enter = span.enter();
+
+    // one structured record per request, alert or not
+    if cfg.emit_request_summary {
+        tracing::info!(
+            target: "moniof::summary",
+            route = route.as_deref().unwrap_or("unmatched"),
+            method = %method,
+            status,
+            total = a.total,
+            reads = a.reads,
+            writes = a.writes,
+            db_total_ms = a.db_total_ms,
+            db_busy_ms = a.db_busy_ms as u64,
+            pool_wait_ms = a.pool_wait_ms as u64,
+            work_ms = a.work_ms as u64,
+            elapsed_ms = a.elapsed_ms,
+            first_query_ms = a.first_query_ms.map(|ms| ms as u64),
+            app_ms = a.app_ms as u64,
+            db_concurrent = a.db_concurrent,
+            slowest_key = slowest_key.map(|(k, _)| k.as_str()),
+            reply_bytes = a.reply_bytes,
+            heaviest_reply_key = a.heaviest_reply.as_ref().map(|(k, _)| k.as_str()),
+            n_plus_one_suspects = a.suspects.len(),
+            "request summary"
+        );
+    }
+
+    // --------------------------
+    // Response headers
+    // --------------------------
+    let mut headers = Vec::new();
+    if cfg.add_response_headers {
+        let max_len = cfg.max_header_value_len;
+        let mut put =
+            |name: &'static str, val: String| headers.push((name, header_value(&val, max_len)));
+
+        put("x-moniof-request-id", request_id.clone());
+        put("x-moniof-total", a.total.to_string());
+        put("x-moniof-reads", a.reads.to_string());
+        put("x-moniof-writes", a.writes.to_string());
+        put("x-moniof-peak-concurrency", a.peak_concurrency.to_string());
+        put("x-moniof-elapsed-ms", a.elapsed_ms.to_string());
+        put("x-moniof-db-total-ms", a.db_total_ms.to_string());
+        put("x-moniof-db-busy-ms", a.db_busy_ms.to_string());
+        put("x-moniof-app-ms", a.app_ms.to_string());
+        if a.db_concurrent {
+            put("x-moniof-db-concurrent", "true".to_string());
+        }
+        if a.pool_wait_ms > 0 {
+            put("x-moniof-pool-wait-ms", a.pool_wait_ms.to_string());
+        }
+        if a.work_ms > 0 {
+            put("x-moniof-work-ms", a.work_ms.to_string());
+        }
+        for (kind, ms) in &a.db_by_kind {
+            let name = match kind {
+                QueryKind::Mongo => "x-moniof-mongo-ms",
+                QueryKind::Sql => "x-moniof-sql-ms",
+                QueryKind::Redis => "x-moniof-redis-ms",
+                QueryKind::Other => "x-moniof-other-ms",
+            };
+            put(name, ms.to_string());
+        }
+
+        if let Some((k, v)) = slowest_key {
+            put("x-moniof-slowest-key", k.clone());
+            put("x-moniof-slowest-latency-ms", v.to_string());
+        }
+        if a.slowest_keys.len() > 1 {
+            for ((k, v), (key_h, ms_h)) in a.slowest_keys.iter().zip(SLOW_KEY_HEADERS) {
+                put(key_h, k.clone());
+                put(ms_h, v.to_string());
+            }
+        }
+
+        if !a.collection_counts.is_empty() {
+            let value =
+                collection_counts_value(&a.collection_counts, cfg.collection_counts_header, max_len);
+            if !value.is_empty() {
+                put("x-moniof-by-collection", value);
+            }
+        }
+
+        if let Some((k, rows)) = a.most_rows.as_ref() {
+            put("x-moniof-most-rows-key", k.clone());
+            put("x-moniof-most-rows", rows.to_string());
+        }
+
+        if let Some((k, bytes)) = a.heaviest_reply.as_ref() {
+            put("x-moniof-reply-bytes", a.reply_bytes.to_string());
+            put("x-moniof-heaviest-reply-key", k.clone());
+            put("x-moniof-heaviest-reply-bytes", bytes.to_string());
+        }
+
+        if cfg.of_mode {
+            if let Some(top) = a.suspects.first() {
+                put("x-moniof-n-plus-one-key", top.key.clone());
+                put("x-moniof-n-plus-one-count", top.count.to_string());
+                put(
+                    "x-moniof-n-plus-one-total-ms",
+                    top.total_latency_ms.to_string(),
+                );
+            }
+        }
+    }
+
+    // --------------------------
+    // Warnings + Slack alerts (OF-style)
+    // --------------------------
+    if cfg.log_warnings {
+        let route_key = route.as_deref().unwrap_or("unmatched");
+        report::warn_and_alert(cfg, &a, req_duration_s, route_key, || {
+            let mut lines = vec![
+                "⚠️ *moniOF alert*".to_string(),
+                format!("• request id: `{}`", request_id),
+                format!("• status: {}", status),
+                format!("• method: {}", method),
+            ];
+            for (name, value) in &a.dimensions {
+                lines.push(format!("• {}: `{}`", name, value));
+            }
+            lines.extend([
+                format!("• total queries: {} ({} reads / {} writes)", a.total, a.reads, a.writes),
+                format!("• req elapsed: {:.3}s", req_duration_s),
+                format!("• db total latency: {} ms", a.db_total_ms),
+                format!("• db busy time: {} ms", a.db_busy_ms),
+                format!("• app time: {} ms", a.app_ms),
+                format!("• peak concurrent queries: {}", a.peak_concurrency),
+            ]);
+            if a.pool_wait_ms > 0 {
+                lines.push(format!("• connection pool wait: {} ms", a.pool_wait_ms));
+            }
+            lines
+        });
+    }
+
+    // custom sink; a panicking hook must not fail the request
+    if cfg.on_request_complete.is_some() || cfg.summary_sink.is_some() {
+        let summary = RequestSummary {
+            request_id: request_id.clone(),
+            method: method.clone(),
+            route: route.clone(),
+            status,
+            total: a.total,
+            reads: a.reads,
+            writes: a.writes,
+            db_total_ms: a.db_total_ms,
+            elapsed_ms: a.elapsed_ms,
+            first_query_ms: a.first_query_ms,
+            app_ms: a.app_ms,
+            db_concurrent: a.db_concurrent,
+            db_busy_ms: a.db_busy_ms,
+            work_ms: a.work_ms,
+            reply_bytes: a.reply_bytes,
+            heaviest_reply_key: a.heaviest_reply.as_ref().map(|(k, _)| k.clone()),
+            suspects: a.suspects.clone(),
+        };
+        if let Some(hook) = cfg.on_request_complete.as_ref() {
+            let ran = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                hook.call(&summary)
+            }));
+            if ran.is_err() {
+                tracing::error!(target = "moniof", "on_request_complete hook panicked");
+            }
+        }
+        if let Some(sink) = cfg.summary_sink.as_ref() {
+            send_summary(sink, summary);
+        }
+    }
+
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_value_replaces_non_visible_ascii() {
+        assert_eq!(header_value("sql/select é\n", 0), "sql/select ??");
+    }
+
+    #[test]
+    fn header_value_truncates_within_max_len() {
+        assert_eq!(header_value("mongo/users/find", 10), "mongo/u...");
+        assert_eq!(header_value("mongo/users/find", 3), "...");
+        assert_eq!(header_value("mongo/users/find", 2), "mo");
+        assert_eq!(header_value("mongo/users/find", 1), "m");
+        assert_eq!(header_value("short", 10), "short");
+    }
+
+    #[test]
+    fn incoming_request_id_is_sanitized() {
+        assert_eq!(request_id(Some("abc-123")), "abc-123");
+        assert_eq!(request_id(Some("x` <!channel> `y\n")), "x <!channel> y?");
+        assert_eq!(request_id(Some(&"a".repeat(500))).len(), MAX_REQUEST_ID_LEN);
+        // nothing left: a fresh ID instead
+        assert_eq!(request_id(Some("``")).len(), 36);
+    }
+}
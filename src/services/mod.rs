@@ -1 +1,10 @@
 pub mod http;
+pub mod job;
+pub mod lifecycle;
+pub(crate) mod report;
+
+#[cfg(feature = "axum")]
+pub mod axum;
+
+#[cfg(feature = "tonic")]
+pub mod grpc;
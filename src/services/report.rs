@@ -0,0 +1,304 @@
+// Post-scope analysis shared by request reports (`lifecycle`) and job
+// reports (`job`): what the finished stats say, the threshold warnings and
+// the alert. The analysis owns its data, so the stats lock is released
+// before any logging, alerting or user hook runs.
+
+use crate::config::{MoniOFConfig, global};
+use crate::core::stats::{QueryKind, QueryRun, QueryStats};
+use crate::observability::{alert, escalation, of};
+use crate::observability::alert::AlertMessage;
+use crate::observability::of::OfSuspect;
+
+/// Snapshot of a finished request's (or job's) stats, reduced to what the
+/// reports need.
+pub(crate) struct Analysis {
+    pub total: usize,
+    pub reads: usize,
+    pub writes: usize,
+    pub peak_concurrency: usize,
+    pub elapsed_ms: i128,
+    pub db_total_ms: u128,
+    pub db_busy_ms: u128,
+    pub pool_wait_ms: u128,
+    /// Time in `measure` blocks (part of `app_ms`).
+    pub work_ms: u128,
+    pub first_query_ms: Option<u128>,
+    /// `elapsed_ms - db_total_ms`, clamped at 0.
+    pub app_ms: u128,
+    /// DB time exceeded wall time (concurrent queries), so `app_ms` was clamped.
+    pub db_concurrent: bool,
+    pub distinct_keys: usize,
+    /// `total` minus queries exempted from `max_total`.
+    pub total_for_max_total: usize,
+    /// Most-repeated key (by count).
+    pub worst_count: Option<(String, usize)>,
+    /// Most-repeated key over its same-key limit: (key, count, limit).
+    pub same_key_over: Option<(String, usize, usize)>,
+    /// Slowest keys by max latency, slowest first (`slowest_key_count`).
+    pub slowest_keys: Vec<(String, u128)>,
+    /// Key that moved the most rows.
+    pub most_rows: Option<(String, u64)>,
+    pub reply_bytes: u64,
+    /// Key that pulled the most reply bytes.
+    pub heaviest_reply: Option<(String, u64)>,
+    pub suspects: Vec<OfSuspect>,
+    /// Longest run of the same key back-to-back (ordered log only).
+    pub longest_run: Option<QueryRun>,
+    pub db_by_kind: Vec<(QueryKind, u128)>,
+    pub dimensions: Vec<(String, String)>,
+    /// Top collections by count (only with `collection_counts_header`).
+    pub collection_counts: Vec<(String, usize)>,
+}
+
+impl Analysis {
+    pub(crate) fn new(stats: &QueryStats, cfg: &MoniOFConfig) -> Self {
+        let elapsed_ms = stats.elapsed().whole_milliseconds().max(0);
+        let db_total_ms = stats.total_db_latency_ms;
+
+        // ties: first key in map order, as before
+        let mut worst_count: Option<(&String, &usize)> = None;
+        for (k, v) in &stats.per_key {
+            if worst_count.map(|(_, c)| v > c).unwrap_or(true) {
+                worst_count = Some((k, v));
+            }
+        }
+
+        // relaxed keys have their own limit
+        let same_key_over = stats
+            .per_key
+            .iter()
+            .map(|(k, v)| (k, *v, stats.max_same_key_for(k, cfg.max_same_key)))
+            .filter(|(_, v, limit)| v > limit)
+            .max_by_key(|(_, v, _)| *v)
+            .map(|(k, v, limit)| (k.clone(), v, limit));
+
+        let mut slowest_keys: Vec<(&String, &u128)> = stats.per_key_max_latency_ms.iter().collect();
+        slowest_keys.sort_unstable_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        slowest_keys.truncate(cfg.slowest_key_count.max(1));
+
+        let collection_counts = if cfg.collection_counts_header > 0 && stats.total > 0 {
+            stats.counts_by_collection()
+        } else {
+            Vec::new()
+        };
+
+        Self {
+            total: stats.total,
+            reads: stats.reads(),
+            writes: stats.writes(),
+            peak_concurrency: stats.peak_concurrency,
+            elapsed_ms,
+            db_total_ms,
+            db_busy_ms: stats.db_busy_ms(),
+            pool_wait_ms: stats.pool_wait_ms,
+            work_ms: stats.total_work_ms(),
+            first_query_ms: stats.first_query_ms,
+            app_ms: (elapsed_ms - db_total_ms as i128).max(0) as u128,
+            db_concurrent: db_total_ms as i128 > elapsed_ms,
+            distinct_keys: stats.per_key.len(),
+            total_for_max_total: stats.total_for_max_total(),
+            worst_count: worst_count.map(|(k, v)| (k.clone(), *v)),
+            same_key_over,
+            slowest_keys: slowest_keys.into_iter().map(|(k, v)| (k.clone(), *v)).collect(),
+            most_rows: stats.most_rows().map(|(k, v)| (k.clone(), *v)),
+            reply_bytes: stats.total_reply_bytes,
+            heaviest_reply: stats.heaviest_reply().map(|(k, v)| (k.clone(), *v)),
+            suspects: of::find_suspects(stats, cfg),
+            longest_run: stats.longest_run().filter(|r| r.count() > 1),
+            db_by_kind: stats.latency_by_kind(),
+            dimensions: stats.dimensions.clone(),
+            collection_counts,
+        }
+    }
+
+    pub(crate) fn slowest_key(&self) -> Option<&(String, u128)> {
+        self.slowest_keys.first()
+    }
+
+    pub(crate) fn dimension(&self, name: &str) -> Option<&str> {
+        self.dimensions.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+}
+
+/// Log the warnings `cfg` enables and, if any fired and escalation says
+/// page (per `route_key`), send one alert. `elapsed_s` is the scope's wall
+/// time (for the dominant-key share); `head` builds the alert's opening
+/// lines and only runs when an alert is sent.
+pub(crate) fn warn_and_alert(
+    cfg: &MoniOFConfig,
+    a: &Analysis,
+    elapsed_s: f64,
+    route_key: &str,
+    head: impl FnOnce() -> Vec<String>,
+) {
+    // alert kinds that fired, for escalation
+    let mut fired: Vec<&'static str> = Vec::new();
+    // latency / N+1 warnings need enough queries to be meaningful
+    let enough_queries = a.total >= cfg.min_total_for_alert;
+    let slowest_key = a.slowest_key();
+
+    // High total query count (possible N+1 overall)
+    if a.total_for_max_total > cfg.max_total {
+        fired.push("max_total");
+        tracing::warn!(
+            target = "moniof",
+            total = a.total,
+            max_total = cfg.max_total,
+            elapsed_ms = a.elapsed_ms,
+            db_total_ms = a.db_total_ms,
+            peak_concurrency = a.peak_concurrency,
+            "High DB query count (possible N+1)"
+        );
+    }
+
+    // Worst key by count (single key repeated a lot)
+    if let Some((k, v, limit)) = a.same_key_over.as_ref().filter(|_| enough_queries) {
+        fired.push("same_key");
+        tracing::warn!(
+            target = "moniof",
+            key = %k,
+            count = %v,
+            max_same_key = limit,
+            "Repeated same DB key (N+1 likely)"
+        );
+    }
+
+    // High cumulative DB latency
+    if let Some(th) = cfg.effective_warn_total_db_latency_ms(&global()) {
+        if enough_queries && a.db_total_ms >= th {
+            fired.push("total_latency");
+            tracing::warn!(
+                target = "moniof",
+                db_total_ms = a.db_total_ms,
+                threshold = th,
+                "High cumulative DB latency in request"
+            );
+        }
+    }
+
+    // Suspiciously *low* DB latency (instrumentation/cache sanity)
+    if let Some(low) = cfg.warn_low_total_db_latency_ms {
+        if a.total > 0 && a.db_total_ms <= low {
+            fired.push("low_latency");
+            tracing::warn!(
+                target = "moniof",
+                total = a.total,
+                db_total_ms = a.db_total_ms,
+                threshold = low,
+                "Suspiciously LOW cumulative DB latency (check instrumentation or cache?)"
+            );
+        }
+    }
+
+    // One pathological query (cumulative thresholds miss it on low-query endpoints)
+    if let (Some(th), Some((k, ms))) = (cfg.warn_slowest_query_ms, slowest_key) {
+        if *ms >= th {
+            fired.push("slowest_query");
+            tracing::warn!(
+                target = "moniof",
+                key = %k,
+                latency_ms = %ms,
+                threshold = th,
+                "Single slow DB query in request"
+            );
+        }
+    }
+
+    // One query that is most of the wall time (not on trivially fast requests)
+    let dominant = match (cfg.warn_dominant_key_fraction, slowest_key) {
+        (Some(frac), Some((k, ms)))
+            if enough_queries
+                && elapsed_s > 0.0
+                && cfg.warn_dominant_key_min_ms.map_or(true, |min| *ms >= min) =>
+        {
+            let share = (*ms as f64 / 1000.0) / elapsed_s;
+            (share >= frac).then_some((k, share, frac))
+        }
+        _ => None,
+    };
+    if let Some((k, share, frac)) = dominant {
+        fired.push("dominant_key");
+        tracing::warn!(
+            target = "moniof",
+            key = %k,
+            share_pct = (share * 100.0).round() as u64,
+            threshold = frac,
+            "One query dominates request time"
+        );
+    }
+
+    // Explicit N+1 suspects (OF-style)
+    if enough_queries && cfg.of_mode && !a.suspects.is_empty() {
+        fired.push("n_plus_one");
+        for s in &a.suspects {
+            tracing::warn!(
+                target = "moniof::of",
+                key = %s.key,
+                count = %s.count,
+                total_latency_ms = %s.total_latency_ms,
+                "Possible N+1 detected (OF-like)"
+            );
+        }
+        if let Some(run) = a.longest_run.as_ref() {
+            tracing::warn!(
+                target = "moniof::of",
+                key = %run.key,
+                first = run.first,
+                last = run.last,
+                "Consecutive identical queries (N+1 loop)"
+            );
+        }
+    }
+
+    // every kind is counted (no short-circuit); page if any crossed its threshold
+    let window = std::time::Duration::from_millis(cfg.escalate_window_ms);
+    let mut page = false;
+    for kind in &fired {
+        page |= escalation::should_page(route_key, kind, cfg.escalate_after, window);
+    }
+    if !page {
+        return;
+    }
+
+    let g = global();
+    if !alert::has_sink(&g) {
+        return;
+    }
+
+    let mut lines = head();
+    if let Some((k, v)) = slowest_key {
+        let over = match cfg.warn_slowest_query_ms {
+            Some(th) if *v >= th => format!(", over {} ms threshold", th),
+            _ => String::new(),
+        };
+        lines.push(format!("• slowest key: `{}` ({} ms{})", k, v, over));
+    }
+    if let Some((k, share, _)) = dominant {
+        lines.push(format!("• dominant query: `{}` was {:.0}% of request time", k, share * 100.0));
+    }
+    for (i, (k, v)) in a.slowest_keys.iter().enumerate().skip(1) {
+        lines.push(format!("    ↳ #{} slowest: `{}` ({} ms)", i + 1, k, v));
+    }
+    if let Some((k, v)) = a.worst_count.as_ref() {
+        lines.push(format!("• worst key (count): `{}` ×{}", k, v));
+    }
+    if let Some((k, rows)) = a.most_rows.as_ref() {
+        lines.push(format!("• most rows: `{}` ({} rows)", k, rows));
+    }
+    if let Some((k, bytes)) = a.heaviest_reply.as_ref() {
+        lines.push(format!(
+            "• reply bytes: {} (heaviest: `{}`, {} bytes)",
+            a.reply_bytes, k, bytes
+        ));
+    }
+    if cfg.of_mode && !a.suspects.is_empty() {
+        lines.push("• *N+1 suspects* (OF-like):".to_string());
+        for s in &a.suspects {
+            lines.push(format!("    ↳ `{}` — {}× ({} ms total)", s.key, s.count, s.total_latency_ms));
+        }
+        if let Some(run) = a.longest_run.as_ref() {
+            lines.push(format!("    ↳ queries {}–{} were all `{}`", run.first, run.last, run.key));
+        }
+    }
+    alert::dispatch(&g, AlertMessage { kinds: fired, text: lines.join("\n") });
+}
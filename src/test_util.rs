@@ -0,0 +1,93 @@
+#![cfg(feature = "test-util")]
+//! Helpers for regression-testing the middleware end to end: run one request
+//! through an actix `App` wrapped with `MoniOF`, with a handler that records
+//! fake queries, and inspect the `x-moniof-*` headers.
+//!
+//! ```ignore
+//! #[actix_web::test]
+//! async fn flags_n_plus_one() {
+//!     let res = moniof::test_util::request_with_queries(
+//!         MoniOFConfig::default(),
+//!         vec![FakeQuery::new(QueryKind::Mongo, "users/find", 12).latency_ms(2)],
+//!     )
+//!     .await;
+//!     assert_eq!(res.header("x-moniof-n-plus-one-count"), Some("12"));
+//! }
+//! ```
+
+use std::collections::BTreeMap;
+
+use actix_web::{test, web, App, HttpResponse};
+
+use crate::config::MoniOFConfig;
+use crate::core::stats::QueryKind;
+use crate::core::task_ctx::{mark, mark_latency};
+use crate::services::http::MoniOF;
+
+pub use crate::observability::alert::MemorySink;
+
+/// Queries the fake handler records: `repeat` times `kind/key`, each taking
+/// `latency_ms`.
+#[derive(Clone, Debug)]
+pub struct FakeQuery {
+    pub kind: QueryKind,
+    pub key: String,
+    pub repeat: usize,
+    pub latency_ms: u128,
+}
+
+impl FakeQuery {
+    pub fn new(kind: QueryKind, key: impl Into<String>, repeat: usize) -> Self {
+        Self { kind, key: key.into(), repeat, latency_ms: 0 }
+    }
+
+    pub fn latency_ms(mut self, ms: u128) -> Self {
+        self.latency_ms = ms;
+        self
+    }
+}
+
+/// Status and `x-moniof-*` headers of a test request.
+#[derive(Clone, Debug)]
+pub struct TestResponse {
+    pub status: u16,
+    pub headers: BTreeMap<String, String>,
+}
+
+impl TestResponse {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+}
+
+/// Send one `GET /fake` through `MoniOF::with_config(cfg)`; the handler
+/// records `queries` in order. Run inside an actix runtime
+/// (`#[actix_web::test]`).
+pub async fn request_with_queries(cfg: MoniOFConfig, queries: Vec<FakeQuery>) -> TestResponse {
+    let app = test::init_service(App::new().wrap(MoniOF::with_config(cfg)).route(
+        "/fake",
+        web::get().to(move || {
+            let queries = queries.clone();
+            async move {
+                for q in &queries {
+                    for _ in 0..q.repeat {
+                        mark(q.kind, &q.key);
+                        mark_latency(q.kind, &q.key, q.latency_ms);
+                    }
+                }
+                HttpResponse::Ok().finish()
+            }
+        }),
+    ))
+    .await;
+
+    let res = test::call_service(&app, test::TestRequest::get().uri("/fake").to_request()).await;
+    let headers = res
+        .headers()
+        .iter()
+        .filter(|(name, _)| name.as_str().starts_with("x-moniof-"))
+        .filter_map(|(name, v)| Some((name.to_string(), v.to_str().ok()?.to_string())))
+        .collect();
+
+    TestResponse { status: res.status().as_u16(), headers }
+}
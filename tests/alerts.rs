@@ -0,0 +1,51 @@
+// Alert dispatch through `MemorySink`. The sink is global config, so these
+// tests live in their own binary and take `SERIAL` while it's installed.
+
+use moniof::test_util::{FakeQuery, MemorySink, request_with_queries};
+use moniof::{MoniOFConfig, QueryKind};
+use tokio::sync::Mutex;
+
+static SERIAL: Mutex<()> = Mutex::const_new(());
+
+fn install_sink() -> MemorySink {
+    let sink = MemorySink::new();
+    moniof::update_global(|g| g.alert_sink = Some(sink.handle())).unwrap();
+    sink
+}
+
+#[actix_web::test]
+async fn hundred_query_loop_raises_one_n_plus_one_alert() {
+    let _serial = SERIAL.lock().await;
+    let sink = install_sink();
+
+    request_with_queries(
+        MoniOFConfig::default(),
+        vec![FakeQuery::new(QueryKind::Mongo, "users/find", 100).latency_ms(1)],
+    )
+    .await;
+
+    // every kind that fired goes out in a single message
+    assert_eq!(sink.messages().len(), 1);
+    let alerts = sink.with_kind("n_plus_one");
+    assert_eq!(alerts.len(), 1);
+    assert!(alerts[0].text.contains("`mongo/users/find` — 100×"));
+}
+
+#[actix_web::test]
+async fn job_with_a_query_loop_raises_an_n_plus_one_alert() {
+    let _serial = SERIAL.lock().await;
+    let sink = install_sink();
+
+    moniof::instrument_job("nightly-digest", async {
+        for _ in 0..10 {
+            moniof::mark(QueryKind::Mongo, "users/find");
+            moniof::mark_latency(QueryKind::Mongo, "users/find", 2);
+        }
+    })
+    .await;
+
+    let alerts = sink.with_kind("n_plus_one");
+    assert_eq!(alerts.len(), 1);
+    assert!(alerts[0].text.contains("job: `nightly-digest`"));
+    assert!(alerts[0].text.contains("`mongo/users/find` — 10×"));
+}
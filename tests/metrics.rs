@@ -0,0 +1,165 @@
+// Middleware effects on the Prometheus collectors. The collectors and the
+// global config are process-wide, so every test holds `SERIAL` and compares
+// before/after values.
+
+use std::panic::AssertUnwindSafe;
+
+use actix_web::http::Method;
+use actix_web::{test, web, App, HttpResponse};
+use futures_util::FutureExt;
+use moniof::{mark, mark_latency, prom, update_global, MoniOF, MoniOFConfig, QueryKind};
+use tokio::sync::Mutex;
+
+static SERIAL: Mutex<()> = Mutex::const_new(());
+
+fn requests_total() -> u64 {
+    prom::metrics_snapshot().requests_by_status_class.values().sum()
+}
+
+/// Value of the first scraped sample of `metric` whose labels include `label`.
+async fn scraped(metric: &str, label: &str) -> Option<f64> {
+    let body = actix_web::body::to_bytes(prom::metrics_handler().await.into_body()).await.ok()?;
+    let text = String::from_utf8(body.to_vec()).ok()?;
+    text.lines()
+        .filter(|l| l.starts_with(&format!("{metric}{{")) && l.contains(label))
+        .find_map(|l| l.rsplit(' ').next()?.parse().ok())
+}
+
+#[actix_web::test]
+async fn inflight_gauge_returns_to_zero_after_a_handler_panic() {
+    let _serial = SERIAL.lock().await;
+    prom::init_prometheus();
+    let before = prom::metrics_snapshot().inflight_requests;
+
+    let app = test::init_service(App::new().wrap(MoniOF::new()).route(
+        "/boom",
+        web::get().to(|| async {
+            panic!("handler blew up");
+            #[allow(unreachable_code)]
+            HttpResponse::Ok().finish()
+        }),
+    ))
+    .await;
+    let call = test::call_service(&app, test::TestRequest::get().uri("/boom").to_request());
+    assert!(AssertUnwindSafe(call).catch_unwind().await.is_err());
+
+    assert_eq!(prom::metrics_snapshot().inflight_requests, before);
+}
+
+#[actix_web::test]
+async fn skipped_path_leaves_the_request_counter_alone() {
+    let _serial = SERIAL.lock().await;
+    prom::init_prometheus();
+    let cfg = MoniOFConfig::builder().skip_path("/healthz").build();
+    let app = test::init_service(
+        App::new()
+            .wrap(MoniOF::with_config(cfg))
+            .route("/healthz", web::get().to(HttpResponse::Ok))
+            .route("/users", web::get().to(HttpResponse::Ok)),
+    )
+    .await;
+
+    let before = requests_total();
+    test::call_service(&app, test::TestRequest::get().uri("/healthz").to_request()).await;
+    assert_eq!(requests_total(), before);
+
+    // the same app still counts other paths
+    test::call_service(&app, test::TestRequest::get().uri("/users").to_request()).await;
+    assert_eq!(requests_total(), before + 1);
+}
+
+#[actix_web::test]
+async fn sql_only_request_is_observed_under_the_sql_kind() {
+    let _serial = SERIAL.lock().await;
+    prom::init_prometheus();
+    let count = "moniof_db_total_latency_seconds_count";
+    let sql_before = scraped(count, r#"kind="sql""#).await.unwrap_or(0.0);
+    let mongo_before = scraped(count, r#"kind="mongo""#).await.unwrap_or(0.0);
+
+    let app = test::init_service(App::new().wrap(MoniOF::new()).route(
+        "/report",
+        web::get().to(|| async {
+            mark(QueryKind::Sql, "orders/select");
+            mark_latency(QueryKind::Sql, "orders/select", 4);
+            HttpResponse::Ok().finish()
+        }),
+    ))
+    .await;
+    test::call_service(&app, test::TestRequest::get().uri("/report").to_request()).await;
+
+    assert_eq!(scraped(count, r#"kind="sql""#).await, Some(sql_before + 1.0));
+    assert_eq!(scraped(count, r#"kind="mongo""#).await.unwrap_or(0.0), mongo_before);
+}
+
+#[actix_web::test]
+async fn disabled_instrumentation_moves_no_metrics() {
+    let _serial = SERIAL.lock().await;
+    prom::init_prometheus();
+    let count = "moniof_db_total_latency_seconds_count";
+    let app = test::init_service(App::new().wrap(MoniOF::new()).route(
+        "/users",
+        web::get().to(|| async {
+            mark(QueryKind::Mongo, "users/find");
+            mark_latency(QueryKind::Mongo, "users/find", 4);
+            HttpResponse::Ok().finish()
+        }),
+    ))
+    .await;
+
+    update_global(|g| g.enabled = false).unwrap();
+    let requests = requests_total();
+    let db_samples = scraped(count, r#"kind="mongo""#).await;
+    let res = test::call_service(&app, test::TestRequest::get().uri("/users").to_request()).await;
+    update_global(|g| g.enabled = true).unwrap();
+
+    assert!(res.status().is_success());
+    assert!(!res.headers().keys().any(|h| h.as_str().starts_with("x-moniof-")));
+    assert_eq!(requests_total(), requests);
+    assert_eq!(scraped(count, r#"kind="mongo""#).await, db_samples);
+    assert_eq!(prom::metrics_snapshot().inflight_requests, 0);
+}
+
+#[actix_web::test]
+async fn skipped_method_leaves_the_request_counter_alone() {
+    let _serial = SERIAL.lock().await;
+    prom::init_prometheus();
+    let cfg = MoniOFConfig::builder().skip_method("OPTIONS").build();
+    let app = test::init_service(
+        App::new()
+            .wrap(MoniOF::with_config(cfg))
+            .route("/users", web::method(Method::OPTIONS).to(HttpResponse::NoContent))
+            .route("/users", web::get().to(HttpResponse::Ok)),
+    )
+    .await;
+
+    let before = requests_total();
+    let preflight = test::TestRequest::default().method(Method::OPTIONS).uri("/users");
+    let res = test::call_service(&app, preflight.to_request()).await;
+    assert_eq!(res.status(), 204);
+    assert_eq!(requests_total(), before);
+
+    test::call_service(&app, test::TestRequest::get().uri("/users").to_request()).await;
+    assert_eq!(requests_total(), before + 1);
+}
+
+#[actix_web::test]
+async fn request_queries_are_exported_without_a_dimension() {
+    let _serial = SERIAL.lock().await;
+    prom::init_prometheus();
+    let sum = "moniof_request_queries_sum";
+    let before = scraped(sum, r#"dimension="unset""#).await.unwrap_or(0.0);
+
+    let app = test::init_service(App::new().wrap(MoniOF::new()).route(
+        "/users",
+        web::get().to(|| async {
+            for _ in 0..3 {
+                mark(QueryKind::Mongo, "users/find");
+            }
+            HttpResponse::Ok().finish()
+        }),
+    ))
+    .await;
+    test::call_service(&app, test::TestRequest::get().uri("/users").to_request()).await;
+
+    assert_eq!(scraped(sum, r#"dimension="unset""#).await, Some(before + 3.0));
+}
@@ -0,0 +1,142 @@
+// End-to-end middleware tests on the `test_util` harness.
+
+use std::time::Duration;
+
+use actix_web::{test, web, App, HttpResponse};
+use moniof::test_util::{FakeQuery, request_with_queries};
+use moniof::{mark, mark_latency, measure, measure_async, MoniOF, MoniOFConfig, QueryKind};
+
+#[actix_web::test]
+async fn n_plus_one_count_header_matches_repeats() {
+    let res = request_with_queries(
+        MoniOFConfig::default(),
+        // 24 ms total clears the default `n_plus_one_min_total_ms`
+        vec![FakeQuery::new(QueryKind::Mongo, "users/find", 12).latency_ms(2)],
+    )
+    .await;
+
+    assert_eq!(res.status, 200);
+    assert_eq!(res.header("x-moniof-n-plus-one-key"), Some("mongo/users/find"));
+    assert_eq!(res.header("x-moniof-n-plus-one-count"), Some("12"));
+}
+
+#[actix_web::test]
+async fn repeats_below_min_count_are_not_flagged() {
+    let cfg = MoniOFConfig::default();
+    let below = cfg.n_plus_one_min_count - 1;
+    let res = request_with_queries(cfg, vec![FakeQuery::new(QueryKind::Mongo, "users/find", below).latency_ms(2)])
+        .await;
+
+    assert_eq!(res.header("x-moniof-total"), Some(below.to_string().as_str()));
+    assert_eq!(res.header("x-moniof-n-plus-one-count"), None);
+    assert_eq!(res.header("x-moniof-n-plus-one-key"), None);
+}
+
+#[actix_web::test]
+async fn long_and_non_ascii_keys_still_produce_headers() {
+    let cfg = MoniOFConfig::builder().max_header_value_len(24).build();
+    let key = format!("users/find/{}", "ü".repeat(40));
+    let res = request_with_queries(cfg, vec![FakeQuery::new(QueryKind::Mongo, key, 1).latency_ms(3)])
+        .await;
+
+    let slowest = res.header("x-moniof-slowest-key").expect("header written");
+    assert_eq!(slowest, "mongo/users/find/????...");
+    assert_eq!(slowest.len(), 24);
+}
+
+#[actix_web::test]
+async fn zero_sample_rate_records_no_query_stats() {
+    let queries = vec![FakeQuery::new(QueryKind::Mongo, "users/find", 12).latency_ms(2)];
+
+    let unsampled = request_with_queries(MoniOFConfig::builder().sample_rate(0.0).build(), queries.clone()).await;
+    assert_eq!(unsampled.status, 200);
+    assert!(unsampled.headers.is_empty(), "{:?}", unsampled.headers);
+
+    let sampled = request_with_queries(MoniOFConfig::builder().sample_rate(1.0).build(), queries).await;
+    assert_eq!(sampled.header("x-moniof-total"), Some("12"));
+}
+
+#[actix_web::test]
+async fn only_requests_above_the_threshold_are_analyzed() {
+    let cfg = MoniOFConfig::builder().analyze_only_above_ms(30).build();
+    let app = test::init_service(
+        App::new()
+            .wrap(MoniOF::with_config(cfg))
+            .route("/fast", web::get().to(|| async {
+                mark(QueryKind::Mongo, "users/find");
+                mark_latency(QueryKind::Mongo, "users/find", 1);
+                HttpResponse::Ok().finish()
+            }))
+            .route("/slow", web::get().to(|| async {
+                mark(QueryKind::Mongo, "users/find");
+                mark_latency(QueryKind::Mongo, "users/find", 1);
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                HttpResponse::Ok().finish()
+            })),
+    )
+    .await;
+
+    let fast = test::call_service(&app, test::TestRequest::get().uri("/fast").to_request()).await;
+    assert!(fast.headers().get("x-moniof-total").is_none());
+    assert!(fast.headers().get("x-moniof-slowest-key").is_none());
+
+    let slow = test::call_service(&app, test::TestRequest::get().uri("/slow").to_request()).await;
+    assert_eq!(slow.headers().get("x-moniof-total").unwrap(), "1");
+    assert_eq!(slow.headers().get("x-moniof-slowest-key").unwrap(), "mongo/users/find");
+}
+
+#[actix_web::test]
+async fn measured_work_stays_out_of_db_time() {
+    let app = test::init_service(App::new().wrap(MoniOF::new()).route(
+        "/",
+        web::get().to(|| async {
+            mark(QueryKind::Mongo, "users/find");
+            mark_latency(QueryKind::Mongo, "users/find", 3);
+            for _ in 0..12 {
+                measure("render/row", || std::thread::sleep(Duration::from_millis(1)));
+            }
+            measure_async("serialize/page", tokio::time::sleep(Duration::from_millis(10))).await;
+            HttpResponse::Ok().finish()
+        }),
+    ))
+    .await;
+
+    let res = test::call_service(&app, test::TestRequest::get().uri("/").to_request()).await;
+    let header = |name: &str| res.headers().get(name).map(|v| v.to_str().unwrap().to_string());
+
+    assert_eq!(header("x-moniof-total").as_deref(), Some("1"));
+    assert_eq!(header("x-moniof-db-total-ms").as_deref(), Some("3"));
+    assert_eq!(header("x-moniof-db-busy-ms").as_deref(), Some("0"));
+    assert_eq!(header("x-moniof-other-ms"), None);
+    assert_eq!(header("x-moniof-db-concurrent"), None);
+    assert_eq!(header("x-moniof-n-plus-one-key"), None);
+    let work_ms: u128 = header("x-moniof-work-ms").expect("work header").parse().unwrap();
+    assert!(work_ms >= 22, "{work_ms}");
+}
+
+#[actix_web::test]
+async fn nested_middlewares_keep_the_outer_count() {
+    const N: usize = 7;
+    let app = test::init_service(
+        App::new().wrap(MoniOF::new()).service(
+            web::scope("/x").wrap(MoniOF::new()).route(
+                "",
+                web::get().to(|| async {
+                    for i in 0..N {
+                        let key = format!("users/find/{i}");
+                        mark(QueryKind::Mongo, &key);
+                        mark_latency(QueryKind::Mongo, &key, 1);
+                    }
+                    HttpResponse::Ok().finish()
+                }),
+            ),
+        ),
+    )
+    .await;
+
+    let res = test::call_service(&app, test::TestRequest::get().uri("/x").to_request()).await;
+    assert_eq!(res.status(), 200);
+    // written once, by the outer middleware
+    assert_eq!(res.headers().get_all("x-moniof-total").count(), 1);
+    assert_eq!(res.headers().get("x-moniof-total").unwrap(), N.to_string().as_str());
+}
@@ -0,0 +1,144 @@
+// `MOFSqlEvents` fed with synthetic sqlx-style spans. Global config and
+// the Prometheus collectors are shared, so tests take `SERIAL`.
+
+use moniof::core::scoped;
+use moniof::observability::of::find_suspects;
+use moniof::test_util::MemorySink;
+use moniof::{MOFSqlEvents, MoniOFConfig, prom};
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tracing_subscriber::layer::SubscriberExt;
+
+static SERIAL: Mutex<()> = Mutex::const_new(());
+
+/// One query as sqlx reports it: a `sqlx::query` span closed after
+/// (optionally) recording an error.
+fn run_query(sql: &str, error: Option<&str>) {
+    let span = tracing::info_span!(
+        target: "sqlx::query",
+        "sqlx.query",
+        db.statement = sql,
+        error = tracing::field::Empty,
+    );
+    if let Some(error) = error {
+        span.record("error", error);
+    }
+}
+
+#[tokio::test]
+async fn span_with_error_field_is_counted_as_failed() {
+    let _serial = SERIAL.lock().await;
+    prom::init_prometheus();
+    let _sub = tracing::subscriber::set_default(tracing_subscriber::registry().with(MOFSqlEvents::new()));
+    let errors_before = prom::metrics_snapshot().sql_errors;
+
+    let ((), stats) = scoped(async {
+        run_query("SELECT * FROM users WHERE id = $1", Some("connection reset"));
+        run_query("SELECT * FROM users WHERE id = $1", None);
+    })
+    .await;
+
+    // failed queries still count, but only the failure bumps the error counter
+    assert_eq!(stats.per_key.get("sql/users/select"), Some(&2));
+    assert_eq!(prom::metrics_snapshot().sql_errors, errors_before + 1);
+}
+
+#[tokio::test]
+async fn template_grouping_collapses_parameterized_executions() {
+    let _serial = SERIAL.lock().await;
+    moniof::update_global(|g| g.sql_group_by_template = true).unwrap();
+    let _sub = tracing::subscriber::set_default(tracing_subscriber::registry().with(MOFSqlEvents::new()));
+
+    let ((), stats) = scoped(async {
+        for i in 0..10 {
+            // however the driver logged it: bound values, `$1`, `?`
+            let sql = match i % 3 {
+                0 => format!("SELECT * FROM users WHERE id = {i}"),
+                1 => "SELECT * FROM users WHERE id = $1".to_string(),
+                _ => "select * from users where id = ?".to_string(),
+            };
+            run_query(&sql, None);
+        }
+    })
+    .await;
+    moniof::update_global(|g| g.sql_group_by_template = false).unwrap();
+
+    let key = "sql/select * from users where id = ?";
+    assert_eq!(stats.per_key.len(), 1);
+    assert_eq!(stats.per_key.get(key), Some(&10));
+
+    let cfg = MoniOFConfig::builder().n_plus_one_min_total_ms(None).build();
+    let suspects = find_suspects(&stats, &cfg);
+    assert_eq!(suspects.first().map(|s| (s.key.as_str(), s.count)), Some((key, 10)));
+}
+
+#[tokio::test]
+async fn event_inside_a_query_span_is_counted_once() {
+    let _serial = SERIAL.lock().await;
+    let _sub = tracing::subscriber::set_default(tracing_subscriber::registry().with(MOFSqlEvents::new()));
+
+    let ((), stats) = scoped(async {
+        let span = tracing::info_span!(
+            target: "sqlx::query",
+            "sqlx.query",
+            db.statement = "SELECT * FROM users WHERE id = $1",
+        );
+        span.in_scope(|| {
+            tracing::info!(
+                target: "sqlx::query",
+                rows_returned = 1u64,
+                statement = "SELECT * FROM users WHERE id = $1",
+            );
+        });
+    })
+    .await;
+
+    assert_eq!(stats.total, 1);
+    assert_eq!(stats.per_key.get("sql/users/select"), Some(&1));
+    assert_eq!(stats.per_key_rows.get("sql/users/select"), Some(&1));
+}
+
+#[tokio::test]
+async fn standalone_event_is_counted_once() {
+    let _serial = SERIAL.lock().await;
+    let _sub = tracing::subscriber::set_default(tracing_subscriber::registry().with(MOFSqlEvents::new()));
+
+    let ((), stats) = scoped(async {
+        tracing::info!(target: "sqlx::query", statement = "SELECT * FROM users WHERE id = $1");
+    })
+    .await;
+
+    assert_eq!(stats.total, 1);
+    assert_eq!(stats.per_key.get("sql/users/select"), Some(&1));
+}
+
+#[tokio::test]
+async fn slow_threshold_changes_apply_to_the_next_query() {
+    let _serial = SERIAL.lock().await;
+    let _sub = tracing::subscriber::set_default(tracing_subscriber::registry().with(MOFSqlEvents::new()));
+    let sink = MemorySink::new();
+    let before = moniof::global().slow_db_threshold_ms;
+    let slow_query = || {
+        let _span = tracing::info_span!(target: "sqlx::query", "sqlx.query", db.statement = "SELECT 1");
+        std::thread::sleep(Duration::from_millis(20));
+    };
+
+    moniof::update_global(|g| {
+        g.alert_sink = Some(sink.handle());
+        // reading the config from inside the closure must not deadlock
+        g.slow_db_threshold_ms = Some(moniof::global().slow_db_threshold_ms.unwrap_or(0).max(60_000));
+    })
+    .unwrap();
+    slow_query();
+    assert!(sink.with_kind("slow_sql").is_empty());
+
+    moniof::update_global(|g| g.slow_db_threshold_ms = Some(5)).unwrap();
+    slow_query();
+    moniof::update_global(|g| {
+        g.alert_sink = None;
+        g.slow_db_threshold_ms = before;
+    })
+    .unwrap();
+
+    assert_eq!(sink.with_kind("slow_sql").len(), 1);
+}